@@ -0,0 +1,225 @@
+use crate::asset_error::AssetError;
+use crate::assets::Model;
+use crate::render::shader::Shader;
+use crate::render::texture;
+use crate::render::texture::Texture;
+use crate::vfs;
+use crate::vfs::Vfs;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+const WHITE_TEXTURE_KEY: &str = "<white>";
+
+// Hands out shared handles keyed by normalized path, so that a file used in many places is loaded once.
+// Only weak references are kept here, an asset is freed as soon as its last user drops it.
+#[derive(Default)]
+pub struct AssetCache {
+    vfs: Vfs, // Where every asset is read from
+    textures: HashMap<PathBuf, Weak<Texture>>,
+    models: HashMap<PathBuf, Weak<Model>>,
+    shaders: HashMap<PathBuf, Weak<Shader>>,
+}
+
+// What's alive right now, with the GPU memory it takes
+#[derive(Debug, Default, PartialEq)]
+pub struct MemoryReport {
+    pub textures: usize,
+    pub texture_bytes: usize,
+    pub models: usize,
+    pub model_bytes: usize,
+    pub shaders: usize,
+}
+
+impl AssetCache {
+    pub fn new(vfs: Vfs) -> AssetCache {
+        AssetCache {
+            vfs,
+            ..AssetCache::default()
+        }
+    }
+
+    pub fn vfs(&self) -> &Vfs {
+        &self.vfs
+    }
+
+    pub fn texture(&mut self, path: &str) -> Result<Rc<Texture>, AssetError> {
+        let vfs = &self.vfs;
+        get_or_load(&mut self.textures, cache_key(path), || {
+            texture::load_from_file(path, vfs)
+        })
+    }
+
+    // Shared under the source path like any other texture, but made by the given loader, e.g. from a cooked file
+    pub fn texture_with(
+        &mut self,
+        path: &str,
+        load: impl FnOnce() -> Result<Texture, AssetError>,
+    ) -> Result<Rc<Texture>, AssetError> {
+        get_or_load(&mut self.textures, cache_key(path), load)
+    }
+
+    // Stands in for missing textures and colors untextured materials
+    pub fn white_texture(&mut self) -> Rc<Texture> {
+        let key = PathBuf::from(WHITE_TEXTURE_KEY);
+        match cached(&self.textures, &key) {
+            Some(texture) => texture,
+            None => insert(&mut self.textures, key, Texture::white()),
+        }
+    }
+
+    // Models are loaded by the caller, since their loaders fill the cache with textures meanwhile
+    pub fn cached_model(&self, path: &str) -> Option<Rc<Model>> {
+        cached(&self.models, &cache_key(path))
+    }
+
+    pub fn insert_model(&mut self, path: &str, model: Model) -> Rc<Model> {
+        insert(&mut self.models, cache_key(path), model)
+    }
+
+    pub fn shader(&mut self, path: &str, has_geom: bool) -> Result<Rc<Shader>, String> {
+        let vfs = &self.vfs;
+        get_or_load(&mut self.shaders, cache_key(path), || {
+            Shader::from_file(path, has_geom, vfs)
+        })
+    }
+
+    // The next request for these files loads them again, current users keep the old version
+    pub fn forget(&mut self, paths: &[PathBuf]) {
+        for key in paths.iter().map(|p| cache_key_of(p)) {
+            self.textures.remove(&key);
+            self.models.remove(&key);
+        }
+    }
+
+    // Recompiles the live shaders that were loaded from any of the changed files
+    pub fn reload_shaders(
+        &mut self,
+        changed_files: &[PathBuf],
+    ) -> Vec<(PathBuf, Result<(), String>)> {
+        let changed_keys: Vec<PathBuf> = changed_files.iter().map(|f| cache_key_of(f)).collect();
+        let mut reloads: Vec<(PathBuf, Result<(), String>)> = self
+            .shaders
+            .iter()
+            .filter(|(key, _)| changed_keys.contains(key))
+            .filter_map(|(_, shader)| shader.upgrade())
+            .map(|shader| (shader.path().to_path_buf(), shader.reload(&self.vfs)))
+            .collect();
+        reloads.sort_by(|a, b| a.0.cmp(&b.0));
+        reloads
+    }
+
+    pub fn memory_report(&mut self) -> MemoryReport {
+        self.textures.retain(|_, t| t.strong_count() > 0);
+        self.models.retain(|_, m| m.strong_count() > 0);
+        self.shaders.retain(|_, s| s.strong_count() > 0);
+
+        let textures: Vec<Rc<Texture>> = self.textures.values().filter_map(Weak::upgrade).collect();
+        let models: Vec<Rc<Model>> = self.models.values().filter_map(Weak::upgrade).collect();
+        MemoryReport {
+            textures: textures.len(),
+            texture_bytes: textures.iter().map(|t| t.bytes()).sum(),
+            models: models.len(),
+            model_bytes: models.iter().map(|m| m.buffer_bytes()).sum(),
+            shaders: self.shaders.len(),
+        }
+    }
+}
+
+fn get_or_load<T, E>(
+    entries: &mut HashMap<PathBuf, Weak<T>>,
+    key: PathBuf,
+    load: impl FnOnce() -> Result<T, E>,
+) -> Result<Rc<T>, E> {
+    match cached(entries, &key) {
+        Some(asset) => Ok(asset),
+        None => Ok(insert(entries, key, load()?)),
+    }
+}
+
+fn cached<T>(entries: &HashMap<PathBuf, Weak<T>>, key: &Path) -> Option<Rc<T>> {
+    entries.get(key).and_then(Weak::upgrade)
+}
+
+fn insert<T>(entries: &mut HashMap<PathBuf, Weak<T>>, key: PathBuf, asset: T) -> Rc<T> {
+    let asset = Rc::new(asset);
+    entries.insert(key, Rc::downgrade(&asset));
+    asset
+}
+
+fn cache_key(path: &str) -> PathBuf {
+    cache_key_of(Path::new(path))
+}
+
+// "assets/../assets/a.png" and "assets/a.png" are the same file, paths outside the mounts are kept as is
+fn cache_key_of(path: &Path) -> PathBuf {
+    vfs::normalize(path).map_or_else(|| path.to_path_buf(), PathBuf::from)
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} texture(s) {}, {} model(s) {}, {} shader(s)",
+            self.textures,
+            format_bytes(self.texture_bytes),
+            self.models,
+            format_bytes(self.model_bytes),
+            self.shaders
+        )
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const MEGABYTE: f32 = 1024.0 * 1024.0;
+    if bytes as f32 >= MEGABYTE {
+        format!("{:.1} MB", bytes as f32 / MEGABYTE)
+    } else {
+        format!("{:.1} KB", bytes as f32 / 1024.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loads_once_while_in_use() {
+        let mut entries: HashMap<PathBuf, Weak<String>> = HashMap::new();
+        let mut load_count = 0;
+        let mut load = |entries: &mut HashMap<_, _>| {
+            get_or_load(entries, cache_key("assets/../assets/scene.json"), || {
+                load_count += 1;
+                Ok::<_, ()>("scene".to_string())
+            })
+            .unwrap()
+        };
+
+        let first = load(&mut entries);
+        let second = load(&mut entries);
+        assert!(Rc::ptr_eq(&first, &second));
+
+        drop(first);
+        drop(second);
+        load(&mut entries);
+        assert_eq!(load_count, 2);
+        assert_eq!(entries.len(), 1);
+        assert!(entries.contains_key(&cache_key("assets/scene.json")));
+    }
+
+    #[test]
+    fn test_memory_report_format() {
+        let report = MemoryReport {
+            textures: 2,
+            texture_bytes: 3 * 1024 * 1024,
+            models: 1,
+            model_bytes: 512,
+            shaders: 4,
+        };
+        assert_eq!(
+            report.to_string(),
+            "2 texture(s) 3.0 MB, 1 model(s) 0.5 KB, 4 shader(s)"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+// Where in a file the problem is, as precise as the loader can tell
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetLocation {
+    Line { line: usize, column: usize },
+    Field(String), // e.g. "static_object_entries[3].prefab_name"
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssetErrorCause {
+    Io(String),
+    Parse(String),
+    Obj(String),
+    Gltf(String),
+    Image(String),
+    MaterialCountMismatch { models: usize, materials: usize },
+    MissingPrefab(String),
+    MissingTarget(String),
+    MissingTrigger(String),
+    MissingLevel(String),
+    TooManyPointLights { count: usize, max: usize },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetError {
+    pub file: String,
+    pub location: Option<AssetLocation>,
+    pub cause: AssetErrorCause,
+}
+
+impl AssetError {
+    pub fn new(file: &str, cause: AssetErrorCause) -> AssetError {
+        AssetError {
+            file: file.to_string(),
+            location: None,
+            cause,
+        }
+    }
+
+    pub fn at_field(file: &str, field: String, cause: AssetErrorCause) -> AssetError {
+        AssetError {
+            file: file.to_string(),
+            location: Some(AssetLocation::Field(field)),
+            cause,
+        }
+    }
+
+    pub fn from_io(file: &str, error: &std::io::Error) -> AssetError {
+        AssetError::new(file, AssetErrorCause::Io(error.to_string()))
+    }
+
+    pub fn from_json(file: &str, error: &serde_json::Error) -> AssetError {
+        AssetError {
+            file: file.to_string(),
+            location: Some(AssetLocation::Line {
+                line: error.line(),
+                column: error.column(),
+            }),
+            cause: AssetErrorCause::Parse(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AssetLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetLocation::Line { line, column } => write!(f, "{}:{}", line, column),
+            AssetLocation::Field(field) => write!(f, "{}", field),
+        }
+    }
+}
+
+impl fmt::Display for AssetErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssetErrorCause::Io(e) => write!(f, "couldn't be read: {}", e),
+            AssetErrorCause::Parse(e) => write!(f, "malformed: {}", e),
+            AssetErrorCause::Obj(e) => write!(f, "invalid obj: {}", e),
+            AssetErrorCause::Gltf(e) => write!(f, "invalid gltf: {}", e),
+            AssetErrorCause::Image(e) => write!(f, "invalid image: {}", e),
+            AssetErrorCause::MaterialCountMismatch { models, materials } => write!(
+                f,
+                "{} models but {} materials, every model needs its own material",
+                models, materials
+            ),
+            AssetErrorCause::MissingPrefab(name) => {
+                write!(f, "prefab \"{}\" couldn't be found", name)
+            }
+            AssetErrorCause::MissingTarget(name) => {
+                write!(f, "target point \"{}\" couldn't be found", name)
+            }
+            AssetErrorCause::MissingTrigger(name) => {
+                write!(f, "trigger \"{}\" couldn't be found", name)
+            }
+            AssetErrorCause::MissingLevel(name) => {
+                write!(f, "level \"{}\" isn't in the level list", name)
+            }
+            AssetErrorCause::TooManyPointLights { count, max } => write!(
+                f,
+                "{} point lights, only the first {} are rendered",
+                count, max
+            ),
+        }
+    }
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{} ({}): {}", self.file, location, self.cause),
+            None => write!(f, "{}: {}", self.file, self.cause),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+// Collects the problems of a whole load, so that they can be fixed in one go instead of one per run
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub errors: Vec<AssetError>,
+}
+
+impl LoadReport {
+    pub fn new() -> LoadReport {
+        LoadReport::default()
+    }
+
+    pub fn push(&mut self, error: AssetError) {
+        self.errors.push(error);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} asset problem(s):", self.errors.len())?;
+        for error in &self.errors {
+            writeln!(f, "  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_error_keeps_line() {
+        let json_error = serde_json::from_str::<Vec<u32>>("[1,\n2,\nx]").unwrap_err();
+        let error = AssetError::from_json("scene.json", &json_error);
+        assert_eq!(
+            error.location,
+            Some(AssetLocation::Line { line: 3, column: 1 })
+        );
+        assert!(error.to_string().starts_with("scene.json (3:1): malformed"));
+    }
+}
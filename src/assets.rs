@@ -1,12 +1,26 @@
+use crate::asset_cache::AssetCache;
+use crate::asset_error::{AssetError, AssetErrorCause, LoadReport};
+use crate::cooked;
+use crate::cooked::CookedModel;
+use crate::geom::{Aabb, Triangle};
+use crate::gltf_import;
+use crate::light_source::{PointLightSource, MAX_POINT_LIGHTS};
 use crate::mesh;
 use crate::mesh::Mesh;
 use crate::render::material;
 use crate::render::material::Material;
+use crate::render::texture;
+use crate::spawn_point::SpawnPoint;
 use crate::static_object::StaticObject;
+use crate::trigger::{Launch, TargetPoint, Teleporter, Trigger, TriggerKind};
+use crate::vfs::Vfs;
+use crate::water_volume::WaterVolume;
+use crate::world::{SceneObject, World};
 use cgmath::*;
 use serde::*;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 #[derive(Serialize, Deserialize)]
 struct PrefabEntry {
@@ -19,10 +33,35 @@ struct Repository {
     prefab_entries: Vec<PrefabEntry>,
 }
 
+// What a model file turns into, every mesh has its own material and transform within the prefab
+#[derive(Default)]
+pub struct Model {
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<Material>,
+    pub transforms: Vec<Matrix4<f32>>,
+}
+
+impl Model {
+    pub fn buffer_bytes(&self) -> usize {
+        self.materials.iter().map(|m| m.buffer_bytes()).sum()
+    }
+}
+
 pub struct Prefab {
     name: String,
-    meshes: Vec<Mesh>,
-    materials: Vec<Material>,
+    asset_name: String,
+    model: Rc<Model>, // Shared with the other prefabs made from the same file
+    dependencies: Vec<PathBuf>, // The obj and every file it pulls in, for hot reloading
+}
+
+impl Prefab {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,74 +69,721 @@ struct StaticObjectEntry {
     name: String,
     prefab_name: String,
     position: [f32; 3],
+    #[serde(default, skip_serializing_if = "is_zero")]
+    rotation: [f32; 3],
+    #[serde(default = "unit_scale", skip_serializing_if = "is_unit_scale")]
+    scale: [f32; 3],
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    climbable: bool,
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn is_zero(v: &[f32; 3]) -> bool {
+    *v == [0.0, 0.0, 0.0]
+}
+
+fn is_unit_scale(v: &[f32; 3]) -> bool {
+    *v == unit_scale()
 }
 
 #[derive(Serialize, Deserialize)]
-struct Scene {
+struct WaterVolumeEntry {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+struct TriggerEntry {
+    name: String,
+    kind: TriggerKind,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize)]
+struct TargetPointEntry {
+    name: String,
+    position: [f32; 3],
+}
+
+// Fields are written in this order when saving
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    #[serde(default)]
+    spawn_point: SpawnPoint,
+    #[serde(default = "default_kill_height")]
+    kill_height: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_level: Option<String>, // Loaded once the run is finished, by its name in the level list
     static_object_entries: Vec<StaticObjectEntry>,
+    #[serde(default)]
+    water_volume_entries: Vec<WaterVolumeEntry>,
+    #[serde(default)]
+    trigger_entries: Vec<TriggerEntry>,
+    #[serde(default)]
+    target_point_entries: Vec<TargetPointEntry>,
+    #[serde(default)]
+    point_light_entries: Vec<PointLightSource>,
 }
 
-pub fn load_prefabs(path: &str) -> Vec<Prefab> {
-    let json_string = fs::read_to_string(path).expect("Unable to read the prefabs file");
-    let json_str = json_string.as_str();
-    let repository: Repository = serde_json::from_str(json_str).expect("Repository error");
-    let mut prefabs = Vec::new();
-    for prefab_entry in repository.prefab_entries {
-        let (meshes, materials) = load_obj(&prefab_entry.asset_name);
-        let prefab = Prefab {
-            name: prefab_entry.name,
-            meshes: meshes,
-            materials: materials,
-        };
-        prefabs.push(prefab);
-    }
+fn default_kill_height() -> f32 {
+    -30.0
+}
 
-    prefabs
+// The scene as read from disk, entries are only parsed one by one so that a broken entry doesn't hide the others
+#[derive(Deserialize)]
+struct SceneFile {
+    #[serde(default)]
+    spawn_point: SpawnPoint,
+    #[serde(default = "default_kill_height")]
+    kill_height: f32,
+    #[serde(default)]
+    next_level: Option<String>,
+    static_object_entries: Vec<serde_json::Value>,
+    #[serde(default)]
+    water_volume_entries: Vec<serde_json::Value>,
+    #[serde(default)]
+    trigger_entries: Vec<serde_json::Value>,
+    #[serde(default)]
+    target_point_entries: Vec<serde_json::Value>,
+    #[serde(default)]
+    point_light_entries: Vec<serde_json::Value>,
 }
 
-pub fn create_static_objects<'a>(path: &str, prefabs: &'a Vec<Prefab>) -> Vec<StaticObject<'a>> {
-    let json_string = fs::read_to_string(path).expect("Unable to read the scene file");
+// Broken entries are reported and left out
+fn parse_entries<T: de::DeserializeOwned>(
+    values: Vec<serde_json::Value>,
+    path: &str,
+    field: &str,
+    report: &mut LoadReport,
+) -> Vec<T> {
+    values
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, value)| match serde_json::from_value(value) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                let cause = AssetErrorCause::Parse(e.to_string());
+                report.push(AssetError::at_field(
+                    path,
+                    format!("{}[{}]", field, i),
+                    cause,
+                ));
+                None
+            }
+        })
+        .collect()
+}
+
+// Only an unreadable prefabs file is an error, broken prefabs are reported and left empty
+pub fn load_prefabs(
+    path: &str,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Result<Vec<Prefab>, AssetError> {
+    let json_string = cache
+        .vfs()
+        .read_to_string(path)
+        .map_err(|e| AssetError::from_io(path, &e))?;
     let json_str = json_string.as_str();
+    let repository: Repository =
+        serde_json::from_str(json_str).map_err(|e| AssetError::from_json(path, &e))?;
+    let prefabs = repository
+        .prefab_entries
+        .into_iter()
+        .map(|entry| load_prefab(entry.name, entry.asset_name, cache, report))
+        .collect();
 
-    let scene: Scene = serde_json::from_str(json_str).unwrap();
+    Ok(prefabs)
+}
 
-    let mut static_objects = Vec::new();
-    for static_object_entry in scene.static_object_entries {
-        let prefab = prefabs
+// Reloads the prefabs built from any of the changed files, or all of them if the prefabs file changed.
+// Files that didn't change come from the cache either way.
+pub fn reload_prefabs(
+    prefabs: &mut Vec<Prefab>,
+    path: &str,
+    changed_files: &[PathBuf],
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Result<(), AssetError> {
+    let is_changed = |prefab: &Prefab| {
+        prefab
+            .dependencies
             .iter()
-            .find(|&p| p.name == static_object_entry.prefab_name)
-            .expect("prefab couldn't be found");
+            .any(|d| changed_files.contains(d))
+    };
+    cache.forget(changed_files);
+    for prefab in prefabs.iter().filter(|p| is_changed(p)) {
+        cache.forget(&[PathBuf::from(&prefab.asset_name)]);
+    }
 
-        let pos = static_object_entry.position;
+    if changed_files.iter().any(|f| f == Path::new(path)) {
+        *prefabs = load_prefabs(path, cache, report)?;
+        return Ok(());
+    }
 
-        for (mesh, material) in prefab.meshes.iter().zip(prefab.materials.iter()) {
-            static_objects.push(StaticObject::new(
-                &mesh,
-                &material,
-                Matrix4::from_translation(Vector3::new(pos[0], pos[1], pos[2])),
+    for prefab in prefabs.iter_mut() {
+        if is_changed(prefab) {
+            *prefab = load_prefab(
+                prefab.name.clone(),
+                prefab.asset_name.clone(),
+                cache,
+                report,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn load_prefab(
+    name: String,
+    asset_name: String,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Prefab {
+    let is_gltf = match Path::new(&asset_name).extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension == "gltf" || extension == "glb",
+        None => false,
+    };
+    let model = match cache.cached_model(&asset_name) {
+        Some(model) => Ok(model),
+        None => {
+            let model = if is_gltf {
+                gltf_import::load_gltf(&asset_name, cache, report)
+            } else {
+                match load_cooked(&asset_name, cache, report) {
+                    Some(model) => Ok(model),
+                    None => load_obj(&asset_name, cache, report),
+                }
+            };
+            model.map(|model| cache.insert_model(&asset_name, model))
+        }
+    };
+    // Keeping the empty prefab stops the scene from reporting it a second time as missing
+    let model = model.unwrap_or_else(|e| {
+        report.push(e);
+        Rc::new(Model::default())
+    });
+
+    let mut dependencies = {
+        if is_gltf {
+            gltf_import::gltf_dependencies(&asset_name, cache.vfs())
+        } else {
+            mesh::obj_dependencies(&asset_name, cache.vfs())
+        }
+    };
+    dependencies.extend(
+        model
+            .materials
+            .iter()
+            .filter_map(|m| m.texture_path.as_ref().map(PathBuf::from)),
+    );
+
+    Prefab {
+        name,
+        asset_name,
+        model,
+        dependencies,
+    }
+}
+
+// Parsing is separate from building, so that a broken scene can be rejected before anything is torn down.
+// Only an unreadable or malformed file is an error, broken entries are reported and skipped.
+pub fn load_scene(path: &str, vfs: &Vfs, report: &mut LoadReport) -> Result<Scene, AssetError> {
+    let json_string = vfs
+        .read_to_string(path)
+        .map_err(|e| AssetError::from_io(path, &e))?;
+    let file: SceneFile =
+        serde_json::from_str(&json_string).map_err(|e| AssetError::from_json(path, &e))?;
+
+    Ok(Scene {
+        spawn_point: file.spawn_point,
+        kill_height: file.kill_height,
+        next_level: file.next_level,
+        static_object_entries: parse_entries(
+            file.static_object_entries,
+            path,
+            "static_object_entries",
+            report,
+        ),
+        water_volume_entries: parse_entries(
+            file.water_volume_entries,
+            path,
+            "water_volume_entries",
+            report,
+        ),
+        trigger_entries: parse_entries(file.trigger_entries, path, "trigger_entries", report),
+        target_point_entries: parse_entries(
+            file.target_point_entries,
+            path,
+            "target_point_entries",
+            report,
+        ),
+        point_light_entries: parse_entries(
+            file.point_light_entries,
+            path,
+            "point_light_entries",
+            report,
+        ),
+    })
+}
+
+impl Scene {
+    pub fn next_level(&self) -> Option<&str> {
+        self.next_level.as_deref()
+    }
+}
+
+// Entries that refer to missing things are reported and skipped, the rest of the scene still loads
+pub fn build_world<'a>(
+    scene: Scene,
+    path: &str,
+    prefabs: &'a [Prefab],
+    report: &mut LoadReport,
+) -> World<'a> {
+    // Objects with a missing prefab are still kept, so that saving doesn't drop them
+    let objects: Vec<SceneObject> = scene
+        .static_object_entries
+        .into_iter()
+        .map(|entry| SceneObject {
+            name: entry.name,
+            prefab_name: entry.prefab_name,
+            position: entry.position,
+            rotation: entry.rotation,
+            scale: entry.scale,
+            climbable: entry.climbable,
+        })
+        .collect();
+    for (i, object) in objects.iter().enumerate() {
+        if find_prefab(prefabs, &object.prefab_name).is_none() {
+            report.push(AssetError::at_field(
+                path,
+                format!("static_object_entries[{}].prefab_name", i),
+                AssetErrorCause::MissingPrefab(object.prefab_name.clone()),
             ));
         }
     }
+    let static_objects = create_static_objects(&objects, prefabs);
+
+    let water_volumes = scene
+        .water_volume_entries
+        .iter()
+        .map(|entry| WaterVolume::new(to_aabb(entry.min, entry.max)))
+        .collect();
 
+    let (target_point_entries, trigger_entries) =
+        (&scene.target_point_entries, &scene.trigger_entries);
+    let has_target = |name: &String| target_point_entries.iter().any(|t| &t.name == name);
+    let has_trigger = |name: &String| trigger_entries.iter().any(|t| &t.name == name);
+    for (i, entry) in trigger_entries.iter().enumerate() {
+        let missing = match &entry.kind {
+            TriggerKind::Launch(Launch::ToTarget { target, .. }) if !has_target(target) => {
+                Some(AssetErrorCause::MissingTarget(target.clone()))
+            }
+            TriggerKind::Teleport(Teleporter {
+                destination: Some(destination),
+                ..
+            }) if !has_trigger(destination) => {
+                Some(AssetErrorCause::MissingTrigger(destination.clone()))
+            }
+            _ => None,
+        };
+        if let Some(cause) = missing {
+            let field = format!("trigger_entries[{}].kind", i);
+            report.push(AssetError::at_field(path, field, cause));
+        }
+    }
+
+    let triggers = scene
+        .trigger_entries
+        .into_iter()
+        .map(|entry| Trigger::new(entry.name, to_aabb(entry.min, entry.max), entry.kind))
+        .collect();
+
+    // Kept in the world, so that saving doesn't drop them
+    let point_light_count = scene.point_light_entries.len();
+    if point_light_count > MAX_POINT_LIGHTS {
+        report.push(AssetError::at_field(
+            path,
+            "point_light_entries".to_string(),
+            AssetErrorCause::TooManyPointLights {
+                count: point_light_count,
+                max: MAX_POINT_LIGHTS,
+            },
+        ));
+    }
+
+    let target_points = scene
+        .target_point_entries
+        .into_iter()
+        .map(|entry| TargetPoint {
+            name: entry.name,
+            position: entry.position.into(),
+        })
+        .collect();
+
+    World {
+        objects,
+        static_objects,
+        water_volumes,
+        triggers,
+        target_points,
+        point_lights: scene.point_light_entries,
+        spawn_point: scene.spawn_point,
+        kill_height: scene.kill_height,
+        next_level: scene.next_level,
+    }
+}
+
+// For after the objects were edited, so that the collision follows them right away
+pub fn rebuild_static_objects<'a>(world: &mut World<'a>, prefabs: &'a [Prefab]) {
+    world.static_objects = create_static_objects(&world.objects, prefabs);
+}
+
+fn create_static_objects<'a>(
+    objects: &[SceneObject],
+    prefabs: &'a [Prefab],
+) -> Vec<StaticObject<'a>> {
+    let mut static_objects = Vec::new();
+    for (object_index, object) in objects.iter().enumerate() {
+        let model = match find_prefab(prefabs, &object.prefab_name) {
+            Some(prefab) => &prefab.model,
+            None => continue,
+        };
+
+        let object_transform = object.transform();
+        for ((mesh, material), transform) in model
+            .meshes
+            .iter()
+            .zip(model.materials.iter())
+            .zip(model.transforms.iter())
+        {
+            static_objects.push(StaticObject::new(
+                mesh,
+                material,
+                object_transform * transform,
+                object.climbable || material.is_climbable(),
+                object_index,
+            ));
+        }
+    }
     static_objects
 }
 
-fn load_obj(path: &str) -> (Vec<Mesh>, Vec<Material>) {
-    let (tobj_models, tobj_mats) = match tobj::load_obj(&Path::new(path)) {
-        Ok(cube_obj) => cube_obj,
-        Err(e) => panic!("Error during loading models: {}", e),
-    };
+fn find_prefab<'a>(prefabs: &'a [Prefab], name: &str) -> Option<&'a Prefab> {
+    prefabs.iter().find(|p| p.name == name)
+}
+
+// Writes the world back as a scene file, keeping the world's order so that unchanged saves match.
+// The file is written where it's read from, or next to the loose data if it's only in an archive.
+pub fn save_world(world: &World, path: &str, vfs: &Vfs) -> Result<(), AssetError> {
+    let json = scene_to_json(&scene_from_world(world));
+    let file_path = vfs
+        .writable_path(path)
+        .map_err(|e| AssetError::from_io(path, &e))?;
+    fs::write(file_path, json).map_err(|e| AssetError::from_io(path, &e))
+}
+
+fn scene_from_world(world: &World) -> Scene {
+    Scene {
+        spawn_point: world.spawn_point,
+        kill_height: world.kill_height,
+        next_level: world.next_level.clone(),
+        static_object_entries: world
+            .objects
+            .iter()
+            .map(|object| StaticObjectEntry {
+                name: object.name.clone(),
+                prefab_name: object.prefab_name.clone(),
+                position: object.position,
+                rotation: object.rotation,
+                scale: object.scale,
+                climbable: object.climbable,
+            })
+            .collect(),
+        water_volume_entries: world
+            .water_volumes
+            .iter()
+            .map(|volume| WaterVolumeEntry {
+                min: volume.bounds.min.into(),
+                max: volume.bounds.max.into(),
+            })
+            .collect(),
+        trigger_entries: world
+            .triggers
+            .iter()
+            .map(|trigger| TriggerEntry {
+                name: trigger.name.clone(),
+                kind: trigger.kind.clone(),
+                min: trigger.bounds.min.into(),
+                max: trigger.bounds.max.into(),
+            })
+            .collect(),
+        target_point_entries: world
+            .target_points
+            .iter()
+            .map(|target_point| TargetPointEntry {
+                name: target_point.name.clone(),
+                position: target_point.position.into(),
+            })
+            .collect(),
+        point_light_entries: world.point_lights.clone(),
+    }
+}
 
-    assert_eq!(tobj_models.len(), tobj_mats.len());
+// Indented with four spaces like the hand-written scene files
+fn scene_to_json(scene: &Scene) -> String {
+    let mut json = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut json, formatter);
+    scene
+        .serialize(&mut serializer)
+        .expect("Scene serialization");
+    json.push(b'\n');
+    String::from_utf8(json).expect("Scene serialization")
+}
+
+fn to_aabb(min: [f32; 3], max: [f32; 3]) -> Aabb {
+    Aabb::new(
+        Point3::new(min[0], min[1], min[2]),
+        Point3::new(max[0], max[1], max[2]),
+    )
+}
+
+fn load_obj(
+    path: &str,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Result<Model, AssetError> {
+    let (tobj_models, tobj_mats) = mesh::load_obj(path, cache.vfs())
+        .map_err(|e| AssetError::new(path, AssetErrorCause::Obj(e.to_string())))?;
 
-    let mut materials = Vec::new();
-    let mut meshes = Vec::new();
+    if tobj_models.len() != tobj_mats.len() {
+        let cause = AssetErrorCause::MaterialCountMismatch {
+            models: tobj_models.len(),
+            materials: tobj_mats.len(),
+        };
+        return Err(AssetError::new(path, cause));
+    }
+
+    let mut model = Model::default();
     for (tobj_model, tobj_mat) in tobj_models.iter().zip(tobj_mats) {
-        meshes.push(mesh::Mesh::new(&tobj_model.mesh));
+        model.meshes.push(mesh::Mesh::new(&tobj_model.mesh));
+        model.transforms.push(Matrix4::identity());
 
         let (vertex_data, index_data) = mesh::read_vertex_array(&tobj_model.mesh);
-        materials.push(material::Material::new(vertex_data, index_data, tobj_mat));
+        model.materials.push(material::Material::new(
+            vertex_data,
+            index_data,
+            tobj_mat,
+            cache,
+            report,
+        ));
+    }
+
+    Ok(model)
+}
+
+// The model out of its cooked file, None if there's no cooked file or it can't be used.
+// A stale cooked file is only noted, the source is loaded instead until the cooker is run again.
+fn load_cooked(path: &str, cache: &mut AssetCache, report: &mut LoadReport) -> Option<Model> {
+    let cooked_path = cooked::cooked_path(path);
+    let mapped = cache.vfs().map(&cooked_path).ok()?;
+    match CookedModel::parse(&mapped) {
+        Ok(cooked) if !cooked.is_stale(|d| cache.vfs().modified(d)) => {
+            Some(build_cooked_model(&cooked, cache, report))
+        }
+        Ok(_) => {
+            println!(
+                "{} is stale, loading {} instead",
+                cooked_path.display(),
+                path
+            );
+            None
+        }
+        Err(e) => {
+            println!(
+                "{} can't be used ({}), loading {} instead",
+                cooked_path.display(),
+                e,
+                path
+            );
+            None
+        }
+    }
+}
+
+fn build_cooked_model(
+    cooked: &CookedModel,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Model {
+    let mut textures = Vec::new();
+    for cooked_texture in &cooked.textures {
+        let levels: Vec<&[u8]> = cooked_texture.levels.iter().map(|l| l.as_ref()).collect();
+        let texture = cache
+            .texture_with(&cooked_texture.path, || {
+                texture::from_mips(
+                    cooked_texture.width,
+                    cooked_texture.height,
+                    &levels,
+                    &cooked_texture.path,
+                )
+            })
+            .unwrap_or_else(|e| {
+                report.push(e);
+                cache.white_texture()
+            });
+        textures.push((texture, cooked_texture.path.clone()));
+    }
+
+    let mut model = Model::default();
+    for cooked_mesh in &cooked.meshes {
+        let (texture, texture_path) =
+            match cooked_mesh.texture.and_then(|i| textures.get(i as usize)) {
+                Some((texture, texture_path)) => (texture.clone(), Some(texture_path.clone())),
+                None => (cache.white_texture(), None),
+            };
+        model.meshes.push(Mesh {
+            triangles: cooked_mesh
+                .triangles
+                .iter()
+                .map(Triangle::from_array)
+                .collect(),
+        });
+        model.materials.push(Material::from_vertex_bytes(
+            cooked_mesh.material_name.clone(),
+            &cooked_mesh.vertex_data,
+            cooked_mesh.indices.clone(),
+            texture,
+            texture_path,
+            cooked_mesh.tint,
+        ));
+        model.transforms.push(Matrix4::identity());
+    }
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scene_reports_every_broken_entry() {
+        let json = r#"{
+            "static_object_entries": [
+                { "name": "a", "prefab_name": "missing_a", "position": [0, 0, 0] },
+                { "name": "b", "prefab_name": "missing_b", "position": [0, 0, 0] }
+            ],
+            "trigger_entries": [
+                {
+                    "name": "pad",
+                    "kind": { "Launch": { "ToTarget": { "target": "nowhere", "apex_height": 1 } } },
+                    "min": [0, 0, 0],
+                    "max": [1, 1, 1]
+                }
+            ]
+        }"#;
+        let mut report = LoadReport::new();
+        let mut scene: Scene = serde_json::from_str(json).unwrap();
+        let light = PointLightSource {
+            position: [0.0; 3],
+            intensity: 1.0,
+            attenuation: 0.1,
+        };
+        scene.point_light_entries = vec![light; MAX_POINT_LIGHTS + 1];
+        let world = build_world(scene, "scene.json", &[], &mut report);
+
+        assert!(world.static_objects.is_empty());
+        assert_eq!(world.triggers.len(), 1);
+        assert_eq!(world.point_lights.len(), MAX_POINT_LIGHTS + 1);
+        let causes: Vec<_> = report.errors.iter().map(|e| e.cause.clone()).collect();
+        assert_eq!(
+            causes,
+            vec![
+                AssetErrorCause::MissingPrefab("missing_a".to_string()),
+                AssetErrorCause::MissingPrefab("missing_b".to_string()),
+                AssetErrorCause::MissingTarget("nowhere".to_string()),
+                AssetErrorCause::TooManyPointLights {
+                    count: MAX_POINT_LIGHTS + 1,
+                    max: MAX_POINT_LIGHTS,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scene_skips_every_malformed_entry() {
+        let json = r#"{
+            "static_object_entries": [
+                { "name": "a", "prefab_name": "p", "position": [0, 0] },
+                { "name": "b", "prefab_name": "p", "position": [0, 0, 0] }
+            ],
+            "trigger_entries": [
+                { "name": "start", "kind": "Start", "min": [0, 0, 0], "max": [1, 1, 1] },
+                { "name": "pad", "kind": "Nothing", "min": [0, 0, 0], "max": [1, 1, 1] }
+            ],
+            "point_light_entries": [{ "position": [0, 3, 0] }]
+        }"#;
+        let dir = std::env::temp_dir();
+        let path = "hell_assets_test_scene.json";
+        fs::write(dir.join(path), json).unwrap();
+        let mut vfs = Vfs::new();
+        vfs.mount_directory(&dir);
+
+        let mut report = LoadReport::new();
+        let scene = load_scene(path, &vfs, &mut report).unwrap();
+        fs::remove_file(dir.join(path)).unwrap();
+
+        assert_eq!(scene.static_object_entries.len(), 1);
+        assert_eq!(scene.trigger_entries.len(), 1);
+        assert!(scene.point_light_entries.is_empty());
+        let fields: Vec<_> = report
+            .errors
+            .iter()
+            .map(|e| e.location.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "static_object_entries[0]",
+                "trigger_entries[1]",
+                "point_light_entries[0]",
+            ]
+        );
     }
 
-    (meshes, materials)
+    #[test]
+    fn test_saved_scene_loads_back_the_same() {
+        let json = r#"{
+            "static_object_entries": [
+                { "name": "a", "prefab_name": "missing", "position": [1, 2, 3], "climbable": true }
+            ],
+            "water_volume_entries": [{ "min": [0, -5, 0], "max": [10, 0, 10] }],
+            "trigger_entries": [{ "name": "start", "kind": "Start", "min": [0, 0, 0], "max": [1, 1, 1] }],
+            "point_light_entries": [{ "position": [0, 3, 0], "intensity": 1.5, "attenuation": 0.2 }]
+        }"#;
+        let mut report = LoadReport::new();
+        let world = build_world(
+            serde_json::from_str(json).unwrap(),
+            "a.json",
+            &[],
+            &mut report,
+        );
+        let saved = scene_to_json(&scene_from_world(&world));
+
+        let reloaded = build_world(
+            serde_json::from_str(&saved).unwrap(),
+            "b.json",
+            &[],
+            &mut report,
+        );
+        assert_eq!(scene_to_json(&scene_from_world(&reloaded)), saved);
+        assert_eq!(reloaded.objects, world.objects);
+        assert_eq!(reloaded.point_lights, world.point_lights);
+        assert!(saved.contains("\n    \"kill_height\": -30.0,\n"));
+    }
 }
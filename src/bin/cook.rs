@@ -0,0 +1,205 @@
+// Cooks the obj prefabs of every level, or of the given prefab files, into the cooked directory.
+// The game loads a cooked model instead of its source for as long as none of its files change.
+// Sources are read through the same mounts as the game's, so mods are cooked with their overrides.
+extern crate cgmath;
+extern crate serde;
+extern crate serde_json;
+
+use hell::cooked;
+use hell::cooked::{CookedMesh, CookedModel, CookedTexture, Dependency};
+use hell::mesh;
+use hell::vfs::Vfs;
+use image::imageops::FilterType;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const LEVELS_PATH: &str = "assets/levels.json";
+
+// Only the parts of the level list and the prefab files that point to models
+#[derive(Deserialize)]
+struct LevelEntry {
+    prefabs: String,
+}
+
+#[derive(Deserialize)]
+struct LevelManifest {
+    level_entries: Vec<LevelEntry>,
+}
+
+#[derive(Deserialize)]
+struct PrefabEntry {
+    asset_name: String,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    prefab_entries: Vec<PrefabEntry>,
+}
+
+fn main() {
+    let vfs = match Vfs::mount_game_data() {
+        Ok(vfs) => vfs,
+        Err(e) => {
+            eprintln!("Unable to find the game data: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let prefab_files = if args.is_empty() {
+        match read_json::<LevelManifest>(LEVELS_PATH, &vfs) {
+            Ok(manifest) => manifest
+                .level_entries
+                .into_iter()
+                .map(|l| l.prefabs)
+                .collect(),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        args
+    };
+
+    let mut failure_count = 0;
+    let mut asset_names: Vec<String> = Vec::new();
+    for prefab_file in &prefab_files {
+        match read_json::<Repository>(prefab_file, &vfs) {
+            Ok(repository) => {
+                for entry in repository.prefab_entries {
+                    if !asset_names.contains(&entry.asset_name) {
+                        asset_names.push(entry.asset_name);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failure_count += 1;
+            }
+        }
+    }
+
+    for asset_name in &asset_names {
+        if Path::new(asset_name).extension().and_then(|e| e.to_str()) != Some("obj") {
+            println!("Skipped {}, only obj models are cooked", asset_name);
+            continue;
+        }
+        match cook_obj(asset_name, &vfs) {
+            Ok(cooked_path) => println!("Cooked {} into {}", asset_name, cooked_path.display()),
+            Err(e) => {
+                eprintln!("Couldn't cook {}: {}", asset_name, e);
+                failure_count += 1;
+            }
+        }
+    }
+
+    if failure_count > 0 {
+        process::exit(1);
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &str, vfs: &Vfs) -> Result<T, String> {
+    let json_string = vfs
+        .read_to_string(path)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    serde_json::from_str(&json_string).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Pairs models and materials the same way the game's obj loader does
+fn cook_obj(obj_path: &str, vfs: &Vfs) -> Result<PathBuf, String> {
+    // Taken before reading, so that an edit made during cooking leaves the cooked file stale
+    let dependency = |path: &str| Dependency::new(path, vfs.modified(path));
+    let mut dependencies: Vec<Dependency> = mesh::obj_dependencies(obj_path, vfs)
+        .iter()
+        .map(|p| dependency(&p.to_string_lossy()))
+        .collect();
+
+    let (tobj_models, tobj_mats) = mesh::load_obj(obj_path, vfs).map_err(|e| e.to_string())?;
+    if tobj_models.len() != tobj_mats.len() {
+        return Err(format!(
+            "{} models but {} materials, every model needs its own material",
+            tobj_models.len(),
+            tobj_mats.len()
+        ));
+    }
+
+    let mut textures: Vec<CookedTexture> = Vec::new();
+    let mut meshes = Vec::new();
+    for (tobj_model, tobj_mat) in tobj_models.iter().zip(tobj_mats) {
+        let texture = match mesh::diffuse_texture_path(&tobj_mat) {
+            Some(texture_path) => match textures.iter().position(|t| t.path == texture_path) {
+                Some(index) => Some(index as u32),
+                None => {
+                    dependencies.push(dependency(&texture_path));
+                    textures.push(cook_texture(&texture_path, vfs)?);
+                    Some(textures.len() as u32 - 1)
+                }
+            },
+            None => None,
+        };
+
+        // Untextured meshes are the game's white texture tinted with their diffuse color
+        let tint = match texture {
+            Some(_) => [1.0; 4],
+            None => mesh::diffuse_tint(&tobj_mat),
+        };
+        let (vertex_data, indices) = mesh::read_vertex_array(&tobj_model.mesh);
+        let triangles = mesh::Mesh::new(&tobj_model.mesh)
+            .triangles
+            .iter()
+            .map(|t| t.to_array())
+            .collect();
+        meshes.push(CookedMesh {
+            material_name: tobj_mat.name,
+            texture,
+            tint,
+            vertex_data: Cow::Owned(cooked::f32s_to_bytes(&vertex_data)),
+            indices,
+            triangles,
+        });
+    }
+
+    let cooked_model = CookedModel {
+        dependencies,
+        textures,
+        meshes,
+    };
+    let cooked_path = vfs
+        .writable_path(cooked::cooked_path(obj_path))
+        .map_err(|e| e.to_string())?;
+    if let Some(cooked_dir) = cooked_path.parent() {
+        fs::create_dir_all(cooked_dir).map_err(|e| e.to_string())?;
+    }
+    // Replaced instead of overwritten, since a running game may have the old file mapped
+    let temp_path = cooked_path.with_extension("cooked.tmp");
+    fs::write(&temp_path, cooked_model.to_bytes()).map_err(|e| e.to_string())?;
+    fs::rename(&temp_path, &cooked_path).map_err(|e| e.to_string())?;
+    Ok(cooked_path)
+}
+
+// Converted and flipped like the game does on load, with every mip level down to 1x1
+fn cook_texture(path: &str, vfs: &Vfs) -> Result<CookedTexture<'static>, String> {
+    let bytes = vfs.read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let img = image::load_from_memory(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    let mut level = img.flipv().to_rgba8();
+    let (width, height) = level.dimensions();
+    let mut levels = vec![Cow::Owned(level.as_raw().clone())];
+    while level.width() > 1 || level.height() > 1 {
+        let (level_width, level_height) = ((level.width() / 2).max(1), (level.height() / 2).max(1));
+        level = image::imageops::resize(&level, level_width, level_height, FilterType::Triangle);
+        levels.push(Cow::Owned(level.as_raw().clone()));
+    }
+
+    Ok(CookedTexture {
+        path: path.to_string(),
+        width,
+        height,
+        levels,
+    })
+}
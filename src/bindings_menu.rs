@@ -0,0 +1,70 @@
+use crate::input::*;
+use crate::keys::Keys;
+use crate::vfs::Vfs;
+use sdl2::keyboard::Keycode;
+
+// Up/down picks an action, enter grabs the next key, button or wheel notch as an extra binding,
+// backspace clears the action's bindings. Changes are saved right away.
+pub struct BindingsMenu {
+    is_open: bool,
+    selected: usize,
+    is_capturing: bool,
+    bindings_path: String,
+}
+
+impl BindingsMenu {
+    pub fn new(bindings_path: &str) -> BindingsMenu {
+        BindingsMenu {
+            is_open: false,
+            selected: 0,
+            is_capturing: false,
+            bindings_path: bindings_path.to_string(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+        self.is_capturing = false;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn selected(&self) -> Action {
+        ALL_ACTIONS[self.selected]
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.is_capturing
+    }
+
+    pub fn tick(&mut self, keys: &Keys, input: &mut Input, vfs: &Vfs) {
+        if self.is_capturing {
+            match input.first_pressed_binding() {
+                Some(Binding::Key(Keycode::Escape)) => self.is_capturing = false,
+                Some(binding) => {
+                    input.bindings_mut().add(self.selected(), binding);
+                    input.bindings().save(&self.bindings_path, vfs);
+                    self.is_capturing = false;
+                }
+                None => {}
+            }
+            return;
+        }
+
+        if keys.get_key_down(Keycode::Escape) {
+            self.toggle();
+        } else if keys.get_key_down(Keycode::Up) {
+            self.selected = (self.selected + ALL_ACTIONS.len() - 1) % ALL_ACTIONS.len();
+        } else if keys.get_key_down(Keycode::Down) {
+            self.selected = (self.selected + 1) % ALL_ACTIONS.len();
+        } else if keys.get_key_down(Keycode::Return) {
+            // Starts on the next frame, so that this enter press isn't captured
+            self.is_capturing = true;
+        } else if keys.get_key_down(Keycode::Backspace) {
+            input.bindings_mut().clear(self.selected());
+            input.bindings().save(&self.bindings_path, vfs);
+        }
+    }
+}
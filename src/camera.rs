@@ -0,0 +1,208 @@
+use crate::geom::*;
+use crate::input::PlayerInput;
+use crate::look::ViewAngles;
+use crate::physics::raycast;
+use crate::player::{fly_move, Player};
+use crate::player_state::{MovementState, PlayerEvent};
+use crate::settings::CameraSettings;
+use crate::world::World;
+use cgmath::*;
+
+const MAX_SPEED_FOR_EFFECTS: f32 = 0.01; // Horizontal speed where bob and roll are at full strength
+const FOV_SMOOTHING: f32 = 0.005;
+const ROLL_SMOOTHING: f32 = 0.01;
+const DIP_RECOVERY: f32 = 0.008;
+const CROUCH_EYE_DROP: f32 = 0.5;
+const CROUCH_SMOOTHING: f32 = 0.015;
+const CHASE_DISTANCE: f32 = 4.0;
+const CHASE_HEIGHT: f32 = 1.0;
+const CHASE_WALL_MARGIN: f32 = 0.2; // Keeps the near plane from clipping into the wall that was hit
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    FirstPerson,
+    ThirdPerson,
+    FreeFly, // Detached from the player, moves on its own
+}
+
+// First person layers view effects on top of the player's eye,
+// the other modes look at (or ignore) the player from elsewhere
+pub struct Camera {
+    mode: CameraMode,
+    eye: Point3<f32>,
+    view: ViewAngles,
+    settings: CameraSettings,
+    fov: f32,
+    bob_phase: f32,
+    bob_weight: f32,
+    landing_dip: f32,
+    roll: f32,
+    crouch_offset: f32,
+}
+
+impl Camera {
+    pub fn new(settings: CameraSettings) -> Camera {
+        Camera {
+            mode: CameraMode::FirstPerson,
+            eye: Point3::origin(),
+            view: ViewAngles::default(),
+            fov: settings.fov,
+            settings,
+            bob_phase: 0.0,
+            bob_weight: 0.0,
+            landing_dip: 0.0,
+            roll: 0.0,
+            crouch_offset: 0.0,
+        }
+    }
+
+    pub fn cycle_mode(&mut self) {
+        self.mode = match self.mode {
+            CameraMode::FirstPerson => CameraMode::ThirdPerson,
+            CameraMode::ThirdPerson => CameraMode::FreeFly, // Starts from wherever the camera is
+            CameraMode::FreeFly => CameraMode::FirstPerson,
+        };
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    // The free-fly camera eats the input, so that the player doesn't move along with it
+    pub fn controls_player(&self) -> bool {
+        self.mode != CameraMode::FreeFly
+    }
+
+    pub fn tick(
+        &mut self,
+        player: &Player,
+        player_events: &[PlayerEvent],
+        world: &World,
+        input: &PlayerInput,
+        dt: f32,
+    ) {
+        self.update_effects(player, player_events, dt);
+
+        match self.mode {
+            CameraMode::FirstPerson => {
+                self.eye = self.get_first_person_eye(player);
+                self.view = player.view();
+            }
+            CameraMode::ThirdPerson => {
+                let target = player.position() + Vector3::unit_y() * CHASE_HEIGHT;
+                let back = -player.forward();
+
+                // Pull the camera in front of whatever is between it and the player
+                let distance = raycast(&world.static_objects, target, back, CHASE_DISTANCE)
+                    .map(|(hit_distance, _)| (hit_distance - CHASE_WALL_MARGIN).max(0.0))
+                    .unwrap_or(CHASE_DISTANCE);
+
+                self.eye = target + back * distance;
+                self.view = player.view();
+            }
+            CameraMode::FreeFly => {
+                self.view.rotate(input.look);
+                fly_move(&mut self.eye, &self.view.forward(), input, dt);
+            }
+        }
+    }
+
+    fn update_effects(&mut self, player: &Player, player_events: &[PlayerEvent], dt: f32) {
+        let horz_speed = horz(&player.velocity).magnitude();
+        let speed_ratio = (horz_speed / MAX_SPEED_FOR_EFFECTS).min(1.0);
+
+        let target_fov = {
+            let s = &self.settings;
+            if s.speed_fov_enabled && s.speed_fov_max_speed > s.speed_fov_min_speed {
+                let t = (horz_speed - s.speed_fov_min_speed)
+                    / (s.speed_fov_max_speed - s.speed_fov_min_speed);
+                s.fov + s.speed_fov_extra * t.clamp(0.0, 1.0)
+            } else {
+                s.fov
+            }
+        };
+        self.fov += (target_fov - self.fov) * (FOV_SMOOTHING * dt).min(1.0);
+
+        if player.is_grounded() {
+            self.bob_phase += horz_speed * self.settings.view_bob_frequency * dt;
+            self.bob_weight = speed_ratio;
+        } else {
+            self.bob_weight = 0.0;
+        }
+
+        for event in player_events {
+            if let PlayerEvent::Landed { impact_speed } = event {
+                if self.settings.landing_dip_enabled {
+                    let dip = impact_speed * self.settings.landing_dip_scale;
+                    self.landing_dip = self.landing_dip.max(dip.min(self.settings.landing_dip_max));
+                }
+            }
+        }
+        self.landing_dip *= 1.0 - (DIP_RECOVERY * dt).min(1.0);
+
+        let target_crouch_offset = {
+            if player.state() == MovementState::Crouched {
+                CROUCH_EYE_DROP
+            } else {
+                0.0
+            }
+        };
+        self.crouch_offset +=
+            (target_crouch_offset - self.crouch_offset) * (CROUCH_SMOOTHING * dt).min(1.0);
+
+        let right = player.forward().cross(Vector3::unit_y());
+        let strafe_speed = horz_norm(&right)
+            .map(|r| Vector3::dot(player.velocity, r))
+            .unwrap_or(0.0);
+        let target_roll = {
+            if self.settings.strafe_roll_enabled {
+                (strafe_speed / MAX_SPEED_FOR_EFFECTS).clamp(-1.0, 1.0)
+                    * self.settings.strafe_roll_angle
+            } else {
+                0.0
+            }
+        };
+        self.roll += (target_roll - self.roll) * (ROLL_SMOOTHING * dt).min(1.0);
+    }
+
+    fn get_first_person_eye(&self, player: &Player) -> Point3<f32> {
+        let right =
+            horz_norm(&player.forward().cross(Vector3::unit_y())).unwrap_or(Vector3::unit_x());
+
+        let mut eye =
+            player.position() - Vector3::unit_y() * (self.landing_dip + self.crouch_offset);
+        if self.settings.view_bob_enabled {
+            // Vertical bob goes twice as fast as the sideways sway, once per step
+            let amplitude = self.settings.view_bob_amplitude * self.bob_weight;
+            eye += Vector3::unit_y() * (self.bob_phase * 2.0).sin().abs() * amplitude;
+            eye += right * self.bob_phase.cos() * amplitude * 0.5;
+        }
+
+        eye
+    }
+
+    pub fn get_view_matrix(&self) -> Matrix4<f32> {
+        // Only the first person view leans into strafes
+        let roll = match self.mode {
+            CameraMode::FirstPerson => self.roll,
+            _ => 0.0,
+        };
+        self.view.view_matrix(self.eye, roll)
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.view.forward()
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+}
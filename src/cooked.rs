@@ -0,0 +1,318 @@
+// The binary format that the cooker writes prefabs into, so that loading them skips the obj parsing
+// and the image decoding. It's shared by the game and the cooker, so it only depends on std.
+//
+// Everything is little endian. Byte blobs (vertex data, mip levels) are written as they're uploaded,
+// so that they can be handed to the GPU straight out of the memory-mapped file.
+//
+// Collision is cooked as the flat triangle list that physics.rs walks. There's no BVH in here, since the
+// physics doesn't have one to load it into. Adding one is a change to the physics first, then a version bump.
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 8] = b"HELLCOOK";
+pub const VERSION: u32 = 2; // Bump on any change to the layout, older files are then treated as stale
+pub const COOKED_DIR: &str = "cooked";
+
+// A source file with its modification time at cooking, the cooked file is stale once any of them changes.
+// Paths are virtual, see vfs.rs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub path: String,
+    pub modified: u64, // Nanoseconds since the epoch, 0 if the file was missing
+}
+
+// Flipped for OpenGL, with every mip level down to 1x1
+#[derive(Debug, PartialEq)]
+pub struct CookedTexture<'a> {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Cow<'a, [u8]>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CookedMesh<'a> {
+    pub material_name: String,
+    pub texture: Option<u32>,       // Index into CookedModel::textures
+    pub tint: [f32; 4],             // White for textured meshes, see mesh::diffuse_tint
+    pub vertex_data: Cow<'a, [u8]>, // Interleaved like mesh::read_vertex_array
+    pub indices: Vec<u32>,
+    pub triangles: Vec<[f32; 13]>, // Collision triangles, see Triangle::to_array
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CookedModel<'a> {
+    pub dependencies: Vec<Dependency>,
+    pub textures: Vec<CookedTexture<'a>>,
+    pub meshes: Vec<CookedMesh<'a>>,
+}
+
+// Mirrors the source tree, e.g. "assets/ladder.obj" is cooked into "cooked/assets/ladder.obj.cooked"
+pub fn cooked_path(asset_path: &str) -> PathBuf {
+    Path::new(COOKED_DIR).join(format!("{}.cooked", asset_path))
+}
+
+impl Dependency {
+    pub fn new(path: &str, modified: Option<SystemTime>) -> Dependency {
+        Dependency {
+            path: path.to_string(),
+            modified: to_nanos(modified),
+        }
+    }
+}
+
+impl<'a> CookedModel<'a> {
+    // modified looks up the current modification time of a dependency, None if it's missing
+    pub fn is_stale(&self, modified: impl Fn(&str) -> Option<SystemTime>) -> bool {
+        self.dependencies
+            .iter()
+            .any(|d| to_nanos(modified(&d.path)) != d.modified)
+    }
+
+    // Blobs borrow from the data, which is meant to be a memory-mapped file
+    pub fn parse(data: &'a [u8]) -> io::Result<CookedModel<'a>> {
+        let mut reader = Reader { data, position: 0 };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid_data("not a cooked file".to_string()));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(invalid_data(format!(
+                "cooked with version {}, expected {}",
+                version, VERSION
+            )));
+        }
+
+        let dependencies = reader.list(|r| {
+            Ok(Dependency {
+                path: r.string()?,
+                modified: r.u64()?,
+            })
+        })?;
+        let textures = reader.list(|r| {
+            Ok(CookedTexture {
+                path: r.string()?,
+                width: r.u32()?,
+                height: r.u32()?,
+                levels: r.list(|r| r.blob().map(Cow::Borrowed))?,
+            })
+        })?;
+        let meshes = reader.list(|r| {
+            Ok(CookedMesh {
+                material_name: r.string()?,
+                texture: match r.u32()? {
+                    u32::MAX => None,
+                    index => Some(index),
+                },
+                tint: [r.f32()?, r.f32()?, r.f32()?, r.f32()?],
+                vertex_data: Cow::Borrowed(r.blob()?),
+                indices: r.list(|r| r.u32())?,
+                triangles: r.list(|r| {
+                    let mut triangle = [0.0; 13];
+                    for value in triangle.iter_mut() {
+                        *value = r.f32()?;
+                    }
+                    Ok(triangle)
+                })?,
+            })
+        })?;
+
+        Ok(CookedModel {
+            dependencies,
+            textures,
+            meshes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes(MAGIC);
+        writer.u32(VERSION);
+
+        writer.u32(self.dependencies.len() as u32);
+        for dependency in &self.dependencies {
+            writer.string(&dependency.path);
+            writer.u64(dependency.modified);
+        }
+        writer.u32(self.textures.len() as u32);
+        for texture in &self.textures {
+            writer.string(&texture.path);
+            writer.u32(texture.width);
+            writer.u32(texture.height);
+            writer.u32(texture.levels.len() as u32);
+            for level in &texture.levels {
+                writer.blob(level);
+            }
+        }
+        writer.u32(self.meshes.len() as u32);
+        for mesh in &self.meshes {
+            writer.string(&mesh.material_name);
+            writer.u32(mesh.texture.unwrap_or(u32::MAX));
+            for value in &mesh.tint {
+                writer.f32(*value);
+            }
+            writer.blob(&mesh.vertex_data);
+            writer.u32(mesh.indices.len() as u32);
+            for index in &mesh.indices {
+                writer.u32(*index);
+            }
+            writer.u32(mesh.triangles.len() as u32);
+            for value in mesh.triangles.iter().flatten() {
+                writer.f32(*value);
+            }
+        }
+
+        writer.data
+    }
+}
+
+fn to_nanos(time: Option<SystemTime>) -> u64 {
+    time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+pub fn f32s_to_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Length prefixed
+    fn blob(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.blob(string.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position + length;
+        let bytes = self
+            .data
+            .get(self.position..end)
+            .ok_or_else(|| invalid_data("cooked file ends early".to_string()))?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn blob(&mut self) -> io::Result<&'a [u8]> {
+        let length = self.u32()? as usize;
+        self.bytes(length)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let bytes = self.blob()?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    fn list<T>(&mut self, mut read: impl FnMut(&mut Self) -> io::Result<T>) -> io::Result<Vec<T>> {
+        let count = self.u32()?;
+        (0..count).map(|_| read(self)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_model() -> CookedModel<'static> {
+        CookedModel {
+            dependencies: vec![Dependency {
+                path: "assets/box.obj".to_string(),
+                modified: 42,
+            }],
+            textures: vec![CookedTexture {
+                path: "assets/box.png".to_string(),
+                width: 2,
+                height: 1,
+                levels: vec![Cow::Owned(vec![255; 8]), Cow::Owned(vec![128; 4])],
+            }],
+            meshes: vec![CookedMesh {
+                material_name: "ladder".to_string(),
+                texture: Some(0),
+                tint: [1.0, 0.5, 0.25, 1.0],
+                vertex_data: Cow::Owned(f32s_to_bytes(&[1.0; 24])),
+                indices: vec![0, 1, 2],
+                triangles: vec![[0.5; 13]],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let model = test_model();
+        let bytes = model.to_bytes();
+        assert_eq!(CookedModel::parse(&bytes).unwrap(), model);
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_truncated_files() {
+        let mut bytes = test_model().to_bytes();
+        assert!(CookedModel::parse(&bytes[..bytes.len() - 1]).is_err());
+
+        bytes[MAGIC.len()] = VERSION as u8 + 1;
+        assert!(CookedModel::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_changed_dependency_is_stale() {
+        let cooked_at = UNIX_EPOCH + std::time::Duration::from_secs(100);
+        let mut model = test_model();
+        model.dependencies = vec![Dependency::new("assets/box.obj", Some(cooked_at))];
+
+        assert!(!model.is_stale(|_| Some(cooked_at)));
+        assert!(model.is_stale(|_| Some(cooked_at + std::time::Duration::from_nanos(1))));
+        assert!(model.is_stale(|_| None));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::input::PlayerInput;
+use crate::run_timer::{write_record, RECORDS_DIR};
+use crate::spawn_point::SpawnPoint;
+use crate::vfs::Vfs;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+
+const DEMO_MAGIC: &[u8; 4] = b"DEMO";
+const DEMO_VERSION: u8 = 2;
+const FRAME_SIZE: usize = 4 * 5 + 1; // dt, move axis, look and a byte of flags
+const MIN_PLAYBACK_SPEED: f32 = 0.125;
+const MAX_PLAYBACK_SPEED: f32 = 8.0;
+
+const FLAG_JUMP: u8 = 1;
+const FLAG_JUMP_PRESSED: u8 = 1 << 1;
+const FLAG_JUMP_RELEASED: u8 = 1 << 2;
+const FLAG_CROUCH: u8 = 1 << 3;
+const FLAG_FLY_FAST: u8 = 1 << 4;
+const FLAG_TOGGLE_FLY: u8 = 1 << 5;
+const FLAG_RESPAWN: u8 = 1 << 6;
+
+// The player state a demo starts from, the rest is reset with the level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemoStart {
+    pub spawn_point: SpawnPoint,
+    pub is_flying: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemoFrame {
+    pub dt: f32,
+    pub input: PlayerInput,
+}
+
+// The inputs of every simulation tick. Since the simulation only depends on these and on dt,
+// replaying them from the same start reproduces the run exactly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Demo {
+    pub level_name: String,
+    pub start: DemoStart,
+    frames: Vec<DemoFrame>,
+}
+
+impl Demo {
+    pub fn new(level_name: &str, start: DemoStart) -> Demo {
+        Demo {
+            level_name: level_name.to_owned(),
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, input: PlayerInput, dt: f32) {
+        self.frames.push(DemoFrame { dt, input });
+    }
+
+    pub fn load(path: &Path, vfs: &Vfs) -> Option<Demo> {
+        let bytes = vfs.read(path).ok()?;
+        let demo = Demo::decode(&bytes);
+        if demo.is_none() {
+            println!("Invalid demo file {:?}", path);
+        }
+        demo
+    }
+
+    pub fn save(&self, path: &Path, vfs: &Vfs) {
+        if let Err(e) = write_record(path, &self.encode(), vfs) {
+            println!("Couldn't save the demo to {:?}: {}", path, e);
+        }
+    }
+
+    // Little endian: magic, version, level name, start state, then fixed size frames
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64 + self.frames.len() * FRAME_SIZE);
+        bytes.extend_from_slice(DEMO_MAGIC);
+        bytes.push(DEMO_VERSION);
+
+        bytes.extend_from_slice(&(self.level_name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.level_name.as_bytes());
+
+        let spawn_point = &self.start.spawn_point;
+        for value in spawn_point.position.iter().chain(spawn_point.facing.iter()) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.push(self.start.is_flying as u8);
+
+        bytes.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in self.frames.iter() {
+            let input = &frame.input;
+            for value in &[
+                frame.dt,
+                input.move_axis.0,
+                input.move_axis.1,
+                input.look.0,
+                input.look.1,
+            ] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+
+            let flags = [
+                (input.jump, FLAG_JUMP),
+                (input.jump_pressed, FLAG_JUMP_PRESSED),
+                (input.jump_released, FLAG_JUMP_RELEASED),
+                (input.crouch, FLAG_CROUCH),
+                (input.fly_fast, FLAG_FLY_FAST),
+                (input.toggle_fly, FLAG_TOGGLE_FLY),
+                (input.respawn, FLAG_RESPAWN),
+            ]
+            .iter()
+            .filter(|(is_set, _)| *is_set)
+            .fold(0, |flags, (_, flag)| flags | flag);
+            bytes.push(flags);
+        }
+
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Demo> {
+        let mut reader = ByteReader { bytes, offset: 0 };
+        if reader.take(4)? != DEMO_MAGIC || reader.take(1)?[0] != DEMO_VERSION {
+            return None;
+        }
+
+        let name_length = u16::from_le_bytes(reader.take(2)?.try_into().ok()?) as usize;
+        let level_name = String::from_utf8(reader.take(name_length)?.to_vec()).ok()?;
+
+        let spawn_point = SpawnPoint {
+            position: [reader.f32()?, reader.f32()?, reader.f32()?],
+            facing: [reader.f32()?, reader.f32()?, reader.f32()?],
+        };
+        let is_flying = reader.take(1)?[0] != 0;
+
+        let frame_count = u32::from_le_bytes(reader.take(4)?.try_into().ok()?) as usize;
+        // Checked before allocating, so that a broken count can't ask for gigabytes
+        if frame_count > (bytes.len() - reader.offset) / FRAME_SIZE {
+            return None;
+        }
+        let mut frames = Vec::with_capacity(frame_count);
+        for _ in 0..frame_count {
+            let dt = reader.f32()?;
+            let move_axis = (reader.f32()?, reader.f32()?);
+            let look = (reader.f32()?, reader.f32()?);
+            let flags = reader.take(1)?[0];
+
+            let input = PlayerInput {
+                move_axis,
+                look,
+                jump: flags & FLAG_JUMP != 0,
+                jump_pressed: flags & FLAG_JUMP_PRESSED != 0,
+                jump_released: flags & FLAG_JUMP_RELEASED != 0,
+                crouch: flags & FLAG_CROUCH != 0,
+                fly_fast: flags & FLAG_FLY_FAST != 0,
+                toggle_fly: flags & FLAG_TOGGLE_FLY != 0,
+                respawn: flags & FLAG_RESPAWN != 0,
+            };
+            frames.push(DemoFrame { dt, input });
+        }
+
+        Some(Demo {
+            level_name,
+            start: DemoStart {
+                spawn_point,
+                is_flying,
+            },
+            frames,
+        })
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl ByteReader<'_> {
+    fn take(&mut self, count: usize) -> Option<&[u8]> {
+        let slice = self.bytes.get(self.offset..self.offset + count)?;
+        self.offset += count;
+        Some(slice)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+}
+
+// Feeds the recorded frames back at the recorded pace, scaled by the playback speed
+pub struct DemoPlayer {
+    demo: Demo,
+    next_frame: usize,
+    speed: f32,
+    time_budget: f32,
+}
+
+impl DemoPlayer {
+    pub fn new(demo: Demo) -> DemoPlayer {
+        DemoPlayer {
+            demo,
+            next_frame: 0,
+            speed: 1.0,
+            time_budget: 0.0,
+        }
+    }
+
+    // Returns the frames to simulate for this much real time, can be none or several
+    pub fn advance(&mut self, dt: f32) -> Vec<DemoFrame> {
+        self.time_budget += dt * self.speed;
+
+        let mut frames = Vec::new();
+        while let Some(frame) = self.demo.frames.get(self.next_frame) {
+            if frame.dt > self.time_budget {
+                break;
+            }
+
+            self.time_budget -= frame.dt;
+            self.next_frame += 1;
+            frames.push(*frame);
+        }
+        frames
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next_frame >= self.demo.frames.len()
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_PLAYBACK_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed * 0.5).max(MIN_PLAYBACK_SPEED);
+    }
+}
+
+pub fn demo_path(level_name: &str) -> PathBuf {
+    Path::new(RECORDS_DIR).join(format!("{}.demo", level_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_demo() -> Demo {
+        let start = DemoStart {
+            spawn_point: SpawnPoint::default(),
+            is_flying: false,
+        };
+        let mut demo = Demo::new("test", start);
+        for i in 0..4 {
+            let input = PlayerInput {
+                move_axis: (0.5, -0.25),
+                look: (i as f32, -3.0),
+                jump_pressed: i == 1,
+                respawn: i == 3,
+                ..PlayerInput::default()
+            };
+            demo.record(input, 10.0);
+        }
+        demo
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let demo = test_demo();
+        assert_eq!(Demo::decode(&demo.encode()), Some(demo.clone()));
+
+        let mut truncated = demo.encode();
+        truncated.pop();
+        assert_eq!(Demo::decode(&truncated), None);
+
+        let mut huge_count = demo.encode();
+        let count_offset = huge_count.len() - demo.frames.len() * FRAME_SIZE - 4;
+        huge_count[count_offset..count_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Demo::decode(&huge_count), None);
+    }
+
+    #[test]
+    fn test_playback_speed() {
+        let mut player = DemoPlayer::new(test_demo());
+        assert_eq!(player.advance(5.0).len(), 0);
+        assert_eq!(player.advance(5.0).len(), 1);
+
+        player.faster();
+        assert_eq!(player.advance(10.0).len(), 2);
+        assert_eq!(player.advance(10.0).len(), 1);
+        assert!(player.is_finished());
+    }
+}
@@ -0,0 +1,457 @@
+use crate::assets::{rebuild_static_objects, Prefab};
+use crate::camera::{Camera, CameraMode};
+use crate::keys::Keys;
+use crate::physics::raycast;
+use crate::world::{SceneObject, World};
+use cgmath::*;
+use sdl2::keyboard::Keycode;
+
+const PICK_DISTANCE: f32 = 1000.0;
+const PLACE_DISTANCE: f32 = 5.0; // In front of the camera, when the crosshair doesn't hit anything
+const MIN_SCALE: f32 = 0.01;
+const MAX_HISTORY: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+impl GizmoMode {
+    // The grid size when snapping, and the arrow key step
+    fn step(self) -> f32 {
+        match self {
+            GizmoMode::Translate => 0.5,
+            GizmoMode::Rotate => 15.0,
+            GizmoMode::Scale => 0.1,
+        }
+    }
+
+    // Change per mouse count while dragging
+    fn drag_speed(self) -> f32 {
+        match self {
+            GizmoMode::Translate => 0.02,
+            GizmoMode::Rotate => 0.5,
+            GizmoMode::Scale => 0.005,
+        }
+    }
+}
+
+// What the renderer needs to highlight the selected object and draw its gizmo
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub object_index: usize,
+    pub position: Point3<f32>,
+    pub axis: usize,
+}
+
+// Snapshots of the scene objects from before each edit
+#[derive(Default)]
+pub struct EditHistory {
+    undo: Vec<Vec<SceneObject>>,
+    redo: Vec<Vec<SceneObject>>,
+}
+
+impl EditHistory {
+    pub fn push(&mut self, objects: &[SceneObject]) {
+        self.undo.push(objects.to_vec());
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, current: &[SceneObject]) -> Option<Vec<SceneObject>> {
+        let objects = self.undo.pop()?;
+        self.redo.push(current.to_vec());
+        Some(objects)
+    }
+
+    pub fn redo(&mut self, current: &[SceneObject]) -> Option<Vec<SceneObject>> {
+        let objects = self.redo.pop()?;
+        self.undo.push(current.to_vec());
+        Some(objects)
+    }
+}
+
+// A drag edits from the value it started at, so that snapping doesn't eat the small mouse moves
+struct Drag {
+    start_value: f32,
+    distance: f32,            // Mouse counts since the drag started
+    before: Vec<SceneObject>, // Goes to the history when the drag ends, unless nothing changed
+}
+
+// F2 toggles it. The camera flies freely and the game is frozen while it's open.
+// Left click picks what's under the crosshair, 1/2/3 pick translate/rotate/scale, x/y/z the axis.
+// Dragging with the right mouse button or the left/right arrows changes the value, g toggles snapping.
+// Tab picks a prefab and insert places it, ctrl+d duplicates, delete deletes, ctrl+z/ctrl+y undo/redo.
+// Collision follows every change right away, F9 saves the scene.
+pub struct Editor {
+    is_open: bool,
+    selected: Option<usize>,
+    gizmo_mode: GizmoMode,
+    axis: usize,
+    is_snapping: bool,
+    prefab_index: usize,
+    history: EditHistory,
+    drag: Option<Drag>,
+    prev_camera_mode: CameraMode,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            is_open: false,
+            selected: None,
+            gizmo_mode: GizmoMode::Translate,
+            axis: 0,
+            is_snapping: true,
+            prefab_index: 0,
+            history: EditHistory::default(),
+            drag: None,
+            prev_camera_mode: CameraMode::FirstPerson,
+        }
+    }
+
+    pub fn toggle(&mut self, camera: &mut Camera, objects: &[SceneObject]) {
+        self.is_open = !self.is_open;
+        self.finish_drag(objects);
+        if self.is_open {
+            self.prev_camera_mode = camera.mode();
+            camera.set_mode(CameraMode::FreeFly);
+        } else {
+            camera.set_mode(self.prev_camera_mode);
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    // The mouse drives the gizmo instead of the view while dragging
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    pub fn selection(&self, world: &World) -> Option<Selection> {
+        let object_index = self.selected.filter(|_| self.is_open)?;
+        let object = world.objects.get(object_index)?;
+        Some(Selection {
+            object_index,
+            position: Point3::from(object.position),
+            axis: self.axis,
+        })
+    }
+
+    pub fn status_lines(&self, world: &World, prefabs: &[Prefab]) -> Vec<String> {
+        if !self.is_open {
+            return Vec::new();
+        }
+        let snapping = if self.is_snapping {
+            format!("snap {}", self.gizmo_mode.step())
+        } else {
+            "no snap".to_string()
+        };
+        let selected = self
+            .selected
+            .and_then(|i| world.objects.get(i))
+            .map_or("-", |object| object.name.as_str());
+        let prefab = prefabs.get(self.prefab_index).map_or("-", |p| p.name());
+        vec![
+            format!(
+                "EDIT {:?} {} ({})",
+                self.gizmo_mode,
+                ["x", "y", "z"][self.axis],
+                snapping
+            ),
+            format!("selected: {}", selected),
+            format!("prefab: {}", prefab),
+        ]
+    }
+
+    // drag is the horizontal mouse movement while the right button is held
+    pub fn tick<'a>(
+        &mut self,
+        keys: &Keys,
+        clicked: bool,
+        drag: Option<f32>,
+        camera: &Camera,
+        world: &mut World<'a>,
+        prefabs: &'a [Prefab],
+    ) {
+        let is_ctrl_down = keys.get_key(Keycode::LCtrl) || keys.get_key(Keycode::RCtrl);
+        let mut is_changed = false;
+
+        if clicked {
+            self.selected = raycast(
+                &world.static_objects,
+                camera.position(),
+                camera.forward(),
+                PICK_DISTANCE,
+            )
+            .map(|(_, i)| world.static_objects[i].object_index);
+        }
+
+        if is_ctrl_down && keys.get_key_down(Keycode::Z) {
+            self.finish_drag(&world.objects);
+            if let Some(objects) = self.history.undo(&world.objects) {
+                world.objects = objects;
+                is_changed = true;
+            }
+        } else if is_ctrl_down && keys.get_key_down(Keycode::Y) {
+            self.finish_drag(&world.objects);
+            if let Some(objects) = self.history.redo(&world.objects) {
+                world.objects = objects;
+                is_changed = true;
+            }
+        } else if is_ctrl_down && keys.get_key_down(Keycode::D) {
+            if let Some(object) = self.selected.map(|i| world.objects[i].clone()) {
+                self.record(&world.objects);
+                let name = unique_name(&object.name, &world.objects);
+                world.objects.push(SceneObject { name, ..object });
+                self.selected = Some(world.objects.len() - 1);
+                is_changed = true;
+            }
+        } else if keys.get_key_down(Keycode::Delete) {
+            if let Some(i) = self.selected.take() {
+                self.record(&world.objects);
+                world.objects.remove(i);
+                is_changed = true;
+            }
+        } else if keys.get_key_down(Keycode::Tab) && !prefabs.is_empty() {
+            self.prefab_index = (self.prefab_index + 1) % prefabs.len();
+        } else if keys.get_key_down(Keycode::Insert) {
+            if let Some(prefab) = prefabs.get(self.prefab_index) {
+                self.record(&world.objects);
+                let position = self.placement_position(camera, world);
+                world.objects.push(SceneObject {
+                    name: unique_name(prefab.name(), &world.objects),
+                    prefab_name: prefab.name().to_string(),
+                    position: position.into(),
+                    rotation: [0.0, 0.0, 0.0],
+                    scale: [1.0, 1.0, 1.0],
+                    climbable: false,
+                });
+                self.selected = Some(world.objects.len() - 1);
+                is_changed = true;
+            }
+        } else if keys.get_key_down(Keycode::Num1) {
+            self.gizmo_mode = GizmoMode::Translate;
+        } else if keys.get_key_down(Keycode::Num2) {
+            self.gizmo_mode = GizmoMode::Rotate;
+        } else if keys.get_key_down(Keycode::Num3) {
+            self.gizmo_mode = GizmoMode::Scale;
+        } else if keys.get_key_down(Keycode::X) {
+            self.axis = 0;
+        } else if keys.get_key_down(Keycode::Y) {
+            self.axis = 1;
+        } else if keys.get_key_down(Keycode::Z) {
+            self.axis = 2;
+        } else if keys.get_key_down(Keycode::G) {
+            self.is_snapping = !self.is_snapping;
+        }
+
+        // An undo can leave the selection pointing past the end
+        self.selected = self.selected.filter(|&i| i < world.objects.len());
+        if self.selected.is_none() {
+            self.finish_drag(&world.objects);
+        }
+
+        if let Some(i) = self.selected {
+            let arrow = if keys.get_key_down(Keycode::Left) {
+                -1.0
+            } else if keys.get_key_down(Keycode::Right) {
+                1.0
+            } else {
+                0.0
+            };
+            if arrow != 0.0 {
+                self.record(&world.objects);
+                let object = &mut world.objects[i];
+                let value = gizmo_value(object, self.gizmo_mode, self.axis)
+                    + arrow * self.gizmo_mode.step();
+                self.set_value(object, value);
+                is_changed = true;
+            }
+
+            match drag {
+                Some(distance) => {
+                    let prev_value = gizmo_value(&world.objects[i], self.gizmo_mode, self.axis);
+                    match self.drag.as_mut() {
+                        Some(current) => current.distance += distance,
+                        // The first move only grabs the gizmo
+                        None => {
+                            self.drag = Some(Drag {
+                                start_value: prev_value,
+                                distance: 0.0,
+                                before: world.objects.clone(),
+                            });
+                        }
+                    }
+                    let object = &mut world.objects[i];
+                    let value = self.drag.as_ref().map_or(prev_value, |d| {
+                        d.start_value + d.distance * self.gizmo_mode.drag_speed()
+                    });
+                    self.set_value(object, value);
+                    is_changed |= gizmo_value(object, self.gizmo_mode, self.axis) != prev_value;
+                }
+                None => self.finish_drag(&world.objects),
+            }
+        }
+
+        if is_changed {
+            rebuild_static_objects(world, prefabs);
+        }
+    }
+
+    // Ends any drag first, so that the history stays in the order the edits were made
+    fn record(&mut self, objects: &[SceneObject]) {
+        self.finish_drag(objects);
+        self.history.push(objects);
+    }
+
+    fn finish_drag(&mut self, objects: &[SceneObject]) {
+        if let Some(drag) = self.drag.take() {
+            if drag.before != objects {
+                self.history.push(&drag.before);
+            }
+        }
+    }
+
+    fn set_value(&self, object: &mut SceneObject, value: f32) {
+        let step = Some(self.gizmo_mode.step()).filter(|_| self.is_snapping);
+        set_gizmo_value(object, self.gizmo_mode, self.axis, value, step);
+    }
+
+    // Where the crosshair hits something, or a bit ahead of the camera
+    fn placement_position(&self, camera: &Camera, world: &World) -> Point3<f32> {
+        let distance = raycast(
+            &world.static_objects,
+            camera.position(),
+            camera.forward(),
+            PICK_DISTANCE,
+        )
+        .map_or(PLACE_DISTANCE, |(distance, _)| distance);
+        let position = camera.position() + camera.forward() * distance;
+        if self.is_snapping {
+            let step = GizmoMode::Translate.step();
+            position.map(|v| snap(v, step))
+        } else {
+            position
+        }
+    }
+}
+
+pub fn snap(value: f32, step: f32) -> f32 {
+    (value / step).round() * step
+}
+
+fn gizmo_value(object: &SceneObject, mode: GizmoMode, axis: usize) -> f32 {
+    match mode {
+        GizmoMode::Translate => object.position[axis],
+        GizmoMode::Rotate => object.rotation[axis],
+        GizmoMode::Scale => object.scale[axis],
+    }
+}
+
+// Snaps to the grid when a step is given, rotations wrap around and scales can't flip the object
+fn set_gizmo_value(
+    object: &mut SceneObject,
+    mode: GizmoMode,
+    axis: usize,
+    value: f32,
+    step: Option<f32>,
+) {
+    let value = step.map_or(value, |step| snap(value, step));
+    match mode {
+        GizmoMode::Translate => object.position[axis] = value,
+        GizmoMode::Rotate => object.rotation[axis] = value.rem_euclid(360.0),
+        GizmoMode::Scale => object.scale[axis] = value.max(MIN_SCALE),
+    }
+}
+
+// "box" becomes "box_2", and "box_2" becomes "box_3", skipping names that are already taken
+pub fn unique_name(name: &str, objects: &[SceneObject]) -> String {
+    let base = match name.rfind('_') {
+        Some(i) if name[i + 1..].parse::<u32>().is_ok() => &name[..i],
+        _ => name,
+    };
+    (2..)
+        .map(|n| format!("{}_{}", base, n))
+        .find(|candidate| objects.iter().all(|o| &o.name != candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(name: &str) -> SceneObject {
+        SceneObject {
+            name: name.to_string(),
+            prefab_name: "box_prefab".to_string(),
+            position: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0],
+            scale: [1.0, 1.0, 1.0],
+            climbable: false,
+        }
+    }
+
+    #[test]
+    fn test_gizmo_snaps_wraps_and_clamps() {
+        let mut box_object = object("box");
+        set_gizmo_value(&mut box_object, GizmoMode::Translate, 1, 1.3, Some(0.5));
+        set_gizmo_value(&mut box_object, GizmoMode::Rotate, 1, -15.0, None);
+        set_gizmo_value(&mut box_object, GizmoMode::Scale, 2, -1.0, Some(0.1));
+        assert_eq!(box_object.position, [0.0, 1.5, 0.0]);
+        assert_eq!(box_object.rotation, [0.0, 345.0, 0.0]);
+        assert_eq!(box_object.scale, [1.0, 1.0, MIN_SCALE]);
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let objects = vec![object("box"), object("box_2"), object("ladder")];
+        assert_eq!(unique_name("box", &objects), "box_3");
+        assert_eq!(unique_name("box_2", &objects), "box_3");
+        assert_eq!(unique_name("ladder", &objects), "ladder_2");
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = EditHistory::default();
+        let before = vec![object("box")];
+        let after = vec![object("box"), object("box_2")];
+        history.push(&before);
+
+        assert_eq!(history.undo(&after), Some(before.clone()));
+        assert_eq!(history.undo(&before), None);
+        assert_eq!(history.redo(&before), Some(after.clone()));
+
+        // A new edit drops what could have been redone
+        history.undo(&after);
+        history.push(&before);
+        assert_eq!(history.redo(&before), None);
+    }
+
+    #[test]
+    fn test_drag_back_to_start_is_not_an_edit() {
+        let mut editor = Editor::new();
+        let mut objects = vec![object("box")];
+        let drag = |objects: &[SceneObject]| Drag {
+            start_value: 0.0,
+            distance: 0.0,
+            before: objects.to_vec(),
+        };
+
+        editor.drag = Some(drag(&objects));
+        editor.finish_drag(&objects);
+        assert_eq!(editor.history.undo(&objects), None);
+
+        let before = objects.clone();
+        editor.drag = Some(drag(&objects));
+        objects[0].position = [1.0, 0.0, 0.0];
+        editor.finish_drag(&objects);
+        assert_eq!(editor.history.undo(&objects), Some(before));
+    }
+}
@@ -0,0 +1,74 @@
+use crate::vfs::Vfs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Polls modification times through the vfs, a missing file counts as changed once it appears.
+// A file that a newly mounted or removed mod starts or stops overriding counts as changed as well.
+pub struct FileWatcher {
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    poll_interval: f32, // In milliseconds
+    time_since_poll: f32,
+}
+
+impl FileWatcher {
+    pub fn new(poll_interval: f32) -> FileWatcher {
+        FileWatcher {
+            files: HashMap::new(),
+            poll_interval,
+            time_since_poll: 0.0,
+        }
+    }
+
+    // Watching an already watched file keeps its last seen time, so pending changes aren't lost
+    pub fn watch(&mut self, path: &Path, vfs: &Vfs) {
+        self.files
+            .entry(path.to_path_buf())
+            .or_insert_with(|| vfs.modified(path));
+    }
+
+    // Returns the files that changed since the last poll
+    pub fn poll(&mut self, dt: f32, vfs: &Vfs) -> Vec<PathBuf> {
+        self.time_since_poll += dt;
+        if self.time_since_poll < self.poll_interval {
+            return Vec::new();
+        }
+        self.time_since_poll = 0.0;
+
+        let mut changed = Vec::new();
+        for (path, last_modified) in self.files.iter_mut() {
+            let modified = vfs.modified(path);
+            if modified != *last_modified {
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+        changed.sort();
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_reports_created_file_once() {
+        let dir = std::env::temp_dir();
+        let path = Path::new("hell_file_watcher_test.txt");
+        let _ = fs::remove_file(dir.join(path));
+        let mut vfs = Vfs::new();
+        vfs.mount_directory(&dir);
+
+        let mut watcher = FileWatcher::new(100.0);
+        watcher.watch(path, &vfs);
+        fs::write(dir.join(path), "changed").unwrap();
+
+        assert!(watcher.poll(50.0, &vfs).is_empty());
+        assert_eq!(watcher.poll(50.0, &vfs), vec![path.to_path_buf()]);
+        assert!(watcher.poll(100.0, &vfs).is_empty());
+
+        fs::remove_file(dir.join(path)).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use crate::settings::GamepadSettings;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+const AXIS_MAX: f32 = 32767.0;
+
+// A snapshot of the controller, so that the simulation doesn't need to know about SDL
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadState {
+    pub move_axis: (f32, f32), // x is right, y is forward, after the deadzone
+    pub look_axis: (f32, f32), // x is right, y is down, after the deadzone and the curve
+    pub jump_held: bool,
+    pub jump_pressed: bool,
+    pub jump_released: bool,
+    pub crouch_held: bool,
+}
+
+// Either a face/shoulder button, or an analog trigger pulled past the threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GamepadInput {
+    Button(Button),
+    Trigger(Axis),
+}
+
+pub struct Gamepad {
+    subsystem: GameControllerSubsystem,
+    controller: Option<GameController>,
+    settings: GamepadSettings,
+    jump_input: Option<GamepadInput>,
+    crouch_input: Option<GamepadInput>,
+    state: GamepadState,
+}
+
+impl Gamepad {
+    pub fn new(sdl_context: &sdl2::Sdl, settings: GamepadSettings) -> Gamepad {
+        let jump_input = parse_input(&settings.jump);
+        let crouch_input = parse_input(&settings.crouch);
+
+        // Already connected controllers show up as "added" events on the first poll
+        Gamepad {
+            subsystem: sdl_context.game_controller().unwrap(),
+            controller: None,
+            settings,
+            jump_input,
+            crouch_input,
+            state: GamepadState::default(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } if self.controller.is_none() => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        println!("Gamepad connected: {}", controller.name());
+                        self.controller = Some(controller);
+                    }
+                    Err(e) => println!("Couldn't open gamepad {}: {}", which, e),
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let is_ours = match &self.controller {
+                    Some(controller) => controller.instance_id() == which,
+                    None => false,
+                };
+                if is_ours {
+                    println!("Gamepad disconnected");
+                    self.controller = None;
+                    self.state = GamepadState::default();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let controller = match &self.controller {
+            Some(c) => c,
+            None => return,
+        };
+
+        let read_axis = |axis| controller.axis(axis) as f32 / AXIS_MAX;
+        let is_held = |input: Option<GamepadInput>| match input {
+            Some(GamepadInput::Button(button)) => controller.button(button),
+            Some(GamepadInput::Trigger(axis)) => read_axis(axis) > self.settings.trigger_threshold,
+            None => false,
+        };
+
+        let deadzone = self.settings.deadzone;
+        let (move_x, move_y) =
+            apply_deadzone((read_axis(Axis::LeftX), read_axis(Axis::LeftY)), deadzone);
+        let (look_x, look_y) =
+            apply_deadzone((read_axis(Axis::RightX), read_axis(Axis::RightY)), deadzone);
+
+        let jump_held = is_held(self.jump_input);
+        let crouch_held = is_held(self.crouch_input);
+        let was_jump_held = self.state.jump_held;
+
+        self.state = GamepadState {
+            move_axis: (move_x, -move_y), // Stick up is negative
+            look_axis: (
+                apply_curve(look_x, self.settings.look_curve),
+                apply_curve(look_y, self.settings.look_curve),
+            ),
+            jump_held,
+            jump_pressed: jump_held && !was_jump_held,
+            jump_released: !jump_held && was_jump_held,
+            crouch_held,
+        };
+    }
+
+    pub fn state(&self) -> GamepadState {
+        self.state
+    }
+
+    // Right stick deflection as (yaw, pitch) deltas in degrees
+    pub fn look_delta(&self, dt: f32) -> (f32, f32) {
+        let (x, y) = self.state.look_axis;
+        let scale = self.settings.look_sensitivity * dt;
+        let y_sign = if self.settings.invert_look_y {
+            1.0
+        } else {
+            -1.0
+        };
+        (-x * scale, y * scale * y_sign)
+    }
+}
+
+fn parse_input(name: &str) -> Option<GamepadInput> {
+    match Axis::from_string(name) {
+        Some(axis @ Axis::TriggerLeft) | Some(axis @ Axis::TriggerRight) => {
+            Some(GamepadInput::Trigger(axis))
+        }
+        _ => match Button::from_string(name) {
+            Some(button) => Some(GamepadInput::Button(button)),
+            None => {
+                println!("Unknown gamepad input in settings: {}", name);
+                None
+            }
+        },
+    }
+}
+
+// Radial deadzone, rescaled so that the output starts from zero at the edge of the deadzone
+pub fn apply_deadzone(axis: (f32, f32), deadzone: f32) -> (f32, f32) {
+    let magnitude = (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+    if magnitude <= deadzone || deadzone >= 1.0 {
+        return (0.0, 0.0);
+    }
+
+    let scaled = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0);
+    (axis.0 / magnitude * scaled, axis.1 / magnitude * scaled)
+}
+
+// Exponents above 1 give finer control around the center
+pub fn apply_curve(value: f32, exponent: f32) -> f32 {
+    value.signum() * value.abs().powf(exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadzone() {
+        assert_eq!(apply_deadzone((0.1, 0.05), 0.2), (0.0, 0.0));
+        assert_eq!(apply_deadzone((1.0, 0.0), 0.2), (1.0, 0.0));
+        let (x, y) = apply_deadzone((0.0, -0.6), 0.2);
+        assert_eq!(x, 0.0);
+        assert!((y + 0.5).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_curve() {
+        assert_eq!(apply_curve(0.5, 2.0), 0.25);
+        assert_eq!(apply_curve(-0.5, 2.0), -0.25);
+        assert_eq!(apply_curve(-1.0, 3.0), -1.0);
+    }
+}
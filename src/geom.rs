@@ -26,14 +26,43 @@ impl Triangle {
         let p0 = m.transform_point(self.p0);
         let p1 = m.transform_point(self.p1);
         let p2 = m.transform_point(self.p2);
-        let c = Vector3::cross(self.p1 - self.p0, self.p2 - self.p0);
+        let c = Vector3::cross(p1 - p0, p2 - p0);
 
         Triangle {
             p0: p0,
             p1: p1,
             p2: p2,
             normal: c.normalize(),
-            area: self.area,
+            area: c.magnitude() / 2.0, // Scaling changes it
+        }
+    }
+
+    // Points, normal and area, as stored in cooked files
+    pub fn to_array(self) -> [f32; 13] {
+        [
+            self.p0.x,
+            self.p0.y,
+            self.p0.z,
+            self.p1.x,
+            self.p1.y,
+            self.p1.z,
+            self.p2.x,
+            self.p2.y,
+            self.p2.z,
+            self.normal.x,
+            self.normal.y,
+            self.normal.z,
+            self.area,
+        ]
+    }
+
+    pub fn from_array(a: &[f32; 13]) -> Triangle {
+        Triangle {
+            p0: Point3::new(a[0], a[1], a[2]),
+            p1: Point3::new(a[3], a[4], a[5]),
+            p2: Point3::new(a[6], a[7], a[8]),
+            normal: Vector3::new(a[9], a[10], a[11]),
+            area: a[12],
         }
     }
 }
@@ -58,6 +87,27 @@ impl std::fmt::Debug for Triangle {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Aabb {
+        Aabb {
+            min: Point3::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+            max: Point3::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+        }
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        in_between(point.x, self.min.x, self.max.x)
+            && in_between(point.y, self.min.y, self.max.y)
+            && in_between(point.z, self.min.z, self.max.z)
+    }
+}
+
 pub fn point_triangle_plane_distance(point: Point3<f32>, triangle: Triangle) -> f32 {
     Vector3::dot(point - triangle.p0, triangle.normal).abs()
 }
@@ -211,6 +261,18 @@ mod tests {
         assert!(is_point_in_triangle(Point3::new(0.5, 0.0, 0.0), tri));
     }
 
+    #[test]
+    fn test_is_point_in_scaled_triangle() {
+        let tri = Triangle::new(
+            Point3::new(0.5, -1.0, -1.0),
+            Point3::new(0.5, -1.0, 1.0),
+            Point3::new(0.5, 1.0, 0.0),
+        )
+        .transformed_by(Matrix4::from_scale(2.0));
+
+        assert!(is_point_in_triangle(Point3::new(1.0, 0.0, 0.0), tri));
+    }
+
     #[test]
     fn test_is_point_in_triangle_2() {
         let tri = Triangle::new(
@@ -280,4 +342,13 @@ mod tests {
             Some(0.51)
         );
     }
+
+    #[test]
+    fn test_aabb_contains() {
+        let aabb = Aabb::new(Point3::new(1.0, 0.0, 1.0), Point3::new(-1.0, 2.0, -1.0));
+
+        assert!(aabb.contains(Point3::new(0.0, 1.0, 0.0)));
+        assert!(aabb.contains(Point3::new(1.0, 2.0, -1.0)));
+        assert!(!aabb.contains(Point3::new(0.0, 2.5, 0.0)));
+    }
 }
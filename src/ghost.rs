@@ -0,0 +1,122 @@
+use crate::run_timer::{write_record, RECORDS_DIR};
+use crate::vfs::Vfs;
+use cgmath::*;
+use serde::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GhostFrame {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub forward: [f32; 3],
+}
+
+// Player position and view over the course of a run, keyed by the run timer
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct GhostRun {
+    frames: Vec<GhostFrame>,
+}
+
+impl GhostRun {
+    pub fn load(path: &Path, vfs: &Vfs) -> Option<GhostRun> {
+        let json = vfs.read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    pub fn save(&self, path: &Path, vfs: &Vfs) {
+        let json = serde_json::to_string(self).expect("Ghost serialization");
+        if let Err(e) = write_record(path, json.as_bytes(), vfs) {
+            println!("Couldn't save the ghost to {:?}: {}", path, e);
+        }
+    }
+
+    // Interpolates between the two frames around the given run time
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Vector3<f32>)> {
+        let first = self.frames.first()?;
+        let last = self.frames.last()?;
+        if time <= first.time {
+            return Some(to_pose(first));
+        }
+        if time >= last.time {
+            return Some(to_pose(last));
+        }
+
+        let next_index = self.frames.iter().position(|f| f.time > time)?;
+        let (prev, next) = (&self.frames[next_index - 1], &self.frames[next_index]);
+        let t = (time - prev.time) / (next.time - prev.time);
+
+        let (prev_pos, prev_forward) = to_pose(prev);
+        let (next_pos, next_forward) = to_pose(next);
+        Some((
+            prev_pos + (next_pos - prev_pos) * t,
+            prev_forward.lerp(next_forward, t),
+        ))
+    }
+}
+
+pub struct GhostRecorder {
+    run: GhostRun,
+    is_recording: bool,
+}
+
+impl GhostRecorder {
+    pub fn new() -> GhostRecorder {
+        GhostRecorder {
+            run: GhostRun::default(),
+            is_recording: false,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.run.frames.clear();
+        self.is_recording = true;
+    }
+
+    pub fn record(&mut self, time: f32, position: Point3<f32>, forward: Vector3<f32>) {
+        if self.is_recording {
+            self.run.frames.push(GhostFrame {
+                time,
+                position: position.into(),
+                forward: forward.into(),
+            });
+        }
+    }
+
+    pub fn finish(&mut self) -> GhostRun {
+        self.is_recording = false;
+        std::mem::take(&mut self.run)
+    }
+}
+
+pub fn ghost_path(level_name: &str) -> PathBuf {
+    Path::new(RECORDS_DIR).join(format!("{}.ghost.json", level_name))
+}
+
+fn to_pose(frame: &GhostFrame) -> (Point3<f32>, Vector3<f32>) {
+    (frame.position.into(), frame.forward.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_interpolates_between_frames() {
+        let mut recorder = GhostRecorder::new();
+        recorder.record(0.0, Point3::new(9.0, 9.0, 9.0), Vector3::unit_z());
+        recorder.start();
+        recorder.record(0.0, Point3::new(0.0, 0.0, 0.0), Vector3::unit_z());
+        recorder.record(100.0, Point3::new(10.0, 0.0, 0.0), Vector3::unit_z());
+        let run = recorder.finish();
+
+        assert_eq!(
+            run.sample(25.0),
+            Some((Point3::new(2.5, 0.0, 0.0), Vector3::unit_z()))
+        );
+        assert_eq!(
+            run.sample(500.0),
+            Some((Point3::new(10.0, 0.0, 0.0), Vector3::unit_z()))
+        );
+        assert_eq!(GhostRun::default().sample(0.0), None);
+    }
+}
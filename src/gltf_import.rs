@@ -0,0 +1,415 @@
+use crate::asset_cache::AssetCache;
+use crate::asset_error::{AssetError, AssetErrorCause, LoadReport};
+use crate::assets::Model;
+use crate::mesh;
+use crate::mesh::Mesh;
+use crate::render::material::Material;
+use crate::render::texture;
+use crate::render::texture::Texture;
+use crate::vfs::Vfs;
+use cgmath::*;
+use gltf::Gltf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+// A triangle list with its node's transform baked in from the whole hierarchy above it
+struct Primitive {
+    positions: Vec<f32>,
+    texcoords: Vec<f32>,
+    normals: Vec<f32>,
+    indices: Vec<u32>,
+    transform: Matrix4<f32>,
+    material_index: Option<usize>,
+}
+
+// Loads every mesh in the default scene, primitives that can't be used are reported and skipped
+pub fn load_gltf(
+    path: &str,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Result<Model, AssetError> {
+    let bytes = cache
+        .vfs()
+        .read(path)
+        .map_err(|e| AssetError::from_io(path, &e))?;
+    let gltf = Gltf::from_slice(&bytes).map_err(|e| gltf_error(path, e.to_string()))?;
+    let buffers = load_buffers(&gltf, path, cache.vfs())?;
+
+    let mut model = Model::default();
+    for primitive in read_primitives(&gltf, &buffers, path, report) {
+        let material = primitive
+            .material_index
+            .and_then(|i| gltf.materials().nth(i));
+        let name = material
+            .as_ref()
+            .and_then(|m| m.name())
+            .unwrap_or_default()
+            .to_string();
+        let (texture, texture_path, tint) = match &material {
+            Some(material) => load_base_color(material, &buffers, path, cache, report),
+            None => (cache.white_texture(), None, WHITE),
+        };
+
+        let vertex_data = mesh::interleave_vertices(
+            &primitive.positions,
+            &primitive.texcoords,
+            &primitive.normals,
+        );
+        model
+            .meshes
+            .push(Mesh::from_indexed(&primitive.positions, &primitive.indices));
+        model.materials.push(Material::with_texture(
+            name,
+            vertex_data,
+            primitive.indices,
+            texture,
+            texture_path,
+            tint,
+        ));
+        model.transforms.push(primitive.transform);
+    }
+
+    Ok(model)
+}
+
+// The gltf itself and the external buffers it reads, textures are tracked through the materials
+pub fn gltf_dependencies(path: &str, vfs: &Vfs) -> Vec<PathBuf> {
+    let mut dependencies = vec![PathBuf::from(path)];
+    let gltf = vfs.read(path).ok().and_then(|b| Gltf::from_slice(&b).ok());
+    if let Some(gltf) = gltf {
+        for buffer in gltf.buffers() {
+            if let gltf::buffer::Source::Uri(uri) = buffer.source() {
+                if !uri.starts_with("data:") {
+                    dependencies.push(uri_path(path, uri));
+                }
+            }
+        }
+    }
+    dependencies
+}
+
+fn gltf_error(path: &str, message: String) -> AssetError {
+    AssetError::new(path, AssetErrorCause::Gltf(message))
+}
+
+fn load_buffers(gltf: &Gltf, path: &str, vfs: &Vfs) -> Result<Vec<Vec<u8>>, AssetError> {
+    gltf.buffers()
+        .map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or_else(|| gltf_error(path, "binary chunk is missing".to_string())),
+            gltf::buffer::Source::Uri(uri) => read_uri(uri, path, vfs),
+        })
+        .collect()
+}
+
+// Either an embedded base64 data uri, or a file relative to the gltf
+fn read_uri(uri: &str, path: &str, vfs: &Vfs) -> Result<Vec<u8>, AssetError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let encoded = data
+            .split(";base64,")
+            .nth(1)
+            .ok_or_else(|| gltf_error(path, "data uri isn't base64".to_string()))?;
+        base64::decode(encoded).map_err(|e| gltf_error(path, e.to_string()))
+    } else {
+        let file = uri_path(path, uri);
+        vfs.read(&file)
+            .map_err(|e| AssetError::from_io(&file.to_string_lossy(), &e))
+    }
+}
+
+fn uri_path(gltf_path: &str, uri: &str) -> PathBuf {
+    let gltf_dir = Path::new(gltf_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    gltf_dir.join(uri)
+}
+
+fn read_primitives(
+    gltf: &Gltf,
+    buffers: &[Vec<u8>],
+    path: &str,
+    report: &mut LoadReport,
+) -> Vec<Primitive> {
+    let mut primitives = Vec::new();
+    if let Some(scene) = gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        for node in scene.nodes() {
+            read_node(
+                &node,
+                Matrix4::identity(),
+                buffers,
+                path,
+                report,
+                &mut primitives,
+            );
+        }
+    }
+    primitives
+}
+
+fn read_node(
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    buffers: &[Vec<u8>],
+    path: &str,
+    report: &mut LoadReport,
+    primitives: &mut Vec<Primitive>,
+) {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            match read_primitive(&primitive, buffers) {
+                Ok(mut read) => {
+                    read.transform = transform;
+                    primitives.push(read);
+                }
+                Err(message) => report.push(AssetError::at_field(
+                    path,
+                    format!("meshes[{}].primitives[{}]", mesh.index(), primitive.index()),
+                    AssetErrorCause::Gltf(message),
+                )),
+            }
+        }
+    }
+
+    for child in node.children() {
+        read_node(&child, transform, buffers, path, report, primitives);
+    }
+}
+
+fn read_primitive(primitive: &gltf::Primitive, buffers: &[Vec<u8>]) -> Result<Primitive, String> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        return Err(format!(
+            "{:?} isn't supported, only triangles",
+            primitive.mode()
+        ));
+    }
+
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|b| b.as_slice()));
+    let mut positions: Vec<[f32; 3]> = reader
+        .read_positions()
+        .ok_or("positions are missing")?
+        .collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+
+    // The uv set that the base color texture asks for, flipped since images are flipped on load
+    let uv_set = primitive
+        .material()
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .map_or(0, |info| info.tex_coord());
+    let mut texcoords: Vec<[f32; 2]> = match reader.read_tex_coords(uv_set) {
+        Some(texcoords) => texcoords.into_f32().map(|[u, v]| [u, 1.0 - v]).collect(),
+        None => vec![[0.0, 0.0]; positions.len()],
+    };
+    let mut indices: Vec<u32> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if !indices.len().is_multiple_of(3) {
+        return Err(format!(
+            "{} indices don't make whole triangles",
+            indices.len()
+        ));
+    }
+    if indices.iter().any(|&i| i as usize >= positions.len()) {
+        return Err("indices are out of range".to_string());
+    }
+    let normal_count = normals.as_ref().map_or(positions.len(), |n| n.len());
+    if normal_count != positions.len() || texcoords.len() != positions.len() {
+        return Err(format!(
+            "{} positions but {} normals and {} texcoords",
+            positions.len(),
+            normal_count,
+            texcoords.len()
+        ));
+    }
+    let normals = match normals {
+        Some(normals) => normals,
+        None => flat_shaded(&mut positions, &mut texcoords, &mut indices),
+    };
+
+    Ok(Primitive {
+        positions: positions.iter().flatten().copied().collect(),
+        texcoords: texcoords.iter().flatten().copied().collect(),
+        normals: normals.iter().flatten().copied().collect(),
+        indices,
+        transform: Matrix4::identity(),
+        material_index: primitive.material().index(),
+    })
+}
+
+// Without normals every triangle gets its own vertices, all facing the way the triangle does
+fn flat_shaded(
+    positions: &mut Vec<[f32; 3]>,
+    texcoords: &mut Vec<[f32; 2]>,
+    indices: &mut Vec<u32>,
+) -> Vec<[f32; 3]> {
+    let corners: Vec<usize> = indices.iter().map(|&i| i as usize).collect();
+    *positions = corners.iter().map(|&i| positions[i]).collect();
+    *texcoords = corners.iter().map(|&i| texcoords[i]).collect();
+    *indices = (0..corners.len() as u32).collect();
+
+    positions
+        .chunks_exact(3)
+        .flat_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(triangle[i]));
+            let normal = (b - a).cross(c - a);
+            let normal = if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                normal
+            };
+            vec![normal.into(); 3]
+        })
+        .collect()
+}
+
+// Returns the texture, its file if it isn't embedded, and the base color factor.
+// Only textures in their own files are shared through the cache, embedded ones belong to the material.
+fn load_base_color(
+    material: &gltf::Material,
+    buffers: &[Vec<u8>],
+    path: &str,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> (Rc<Texture>, Option<String>, [f32; 4]) {
+    let pbr = material.pbr_metallic_roughness();
+    let tint = pbr.base_color_factor();
+
+    let (texture, texture_path) = match pbr.base_color_texture() {
+        Some(info) => match info.texture().source().source() {
+            gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+                let texture_path = uri_path(path, uri).to_string_lossy().into_owned();
+                (cache.texture(&texture_path), Some(texture_path))
+            }
+            gltf::image::Source::Uri { uri, .. } => (
+                read_uri(uri, path, cache.vfs())
+                    .and_then(|bytes| texture::load_from_memory(&bytes, path))
+                    .map(Rc::new),
+                None,
+            ),
+            gltf::image::Source::View { view, .. } => {
+                let bytes = buffers
+                    .get(view.buffer().index())
+                    .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                    .ok_or_else(|| {
+                        gltf_error(
+                            path,
+                            format!("image view {} is outside its buffer", view.index()),
+                        )
+                    });
+                (
+                    bytes.and_then(|bytes| texture::load_from_memory(bytes, path).map(Rc::new)),
+                    None,
+                )
+            }
+        },
+        None => (Ok(cache.white_texture()), None),
+    };
+
+    let texture = texture.unwrap_or_else(|e| {
+        report.push(e);
+        cache.white_texture()
+    });
+    (texture, texture_path, tint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One triangle under a translated parent node, with the buffer embedded as a data uri
+    const TRIANGLE_GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [
+            { "translation": [0, 5, 0], "children": [1] },
+            { "translation": [1, 0, 0], "mesh": 0 }
+        ],
+        "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1 } }] }],
+        "buffers": [{ "byteLength": 72, "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAAAAAAIA/AAAAAAAAgD8AAAAAAAAAAAAAgD8AAAAAAAAAAAAAgD8AAAAA" }],
+        "bufferViews": [{ "buffer": 0, "byteLength": 72 }],
+        "accessors": [
+            {
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0, 0, 0], "max": [1, 0, 1]
+            },
+            { "bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_node_transforms_are_combined() {
+        let gltf = Gltf::from_slice(TRIANGLE_GLTF.as_bytes()).unwrap();
+        let buffers = load_buffers(&gltf, "triangle.gltf", &Vfs::new()).unwrap();
+        let mut report = LoadReport::new();
+        let primitives = read_primitives(&gltf, &buffers, "triangle.gltf", &mut report);
+
+        assert!(report.is_empty());
+        assert_eq!(primitives.len(), 1);
+        assert_eq!(primitives[0].indices, vec![0, 1, 2]);
+        assert_eq!(primitives[0].texcoords, vec![0.0; 6]);
+        let origin = primitives[0]
+            .transform
+            .transform_point(Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(origin, Point3::new(1.0, 5.0, 0.0));
+    }
+
+    fn read_broken(gltf_json: &str) -> LoadReport {
+        let gltf = Gltf::from_slice(gltf_json.as_bytes()).unwrap();
+        let buffers = load_buffers(&gltf, "triangle.gltf", &Vfs::new()).unwrap();
+        let mut report = LoadReport::new();
+        let primitives = read_primitives(&gltf, &buffers, "triangle.gltf", &mut report);
+        assert!(primitives.is_empty());
+        report
+    }
+
+    #[test]
+    fn test_partial_triangles_are_reported() {
+        let with_indices = TRIANGLE_GLTF
+            .replace(r#""NORMAL": 1 }"#, r#""NORMAL": 1 }, "indices": 2"#)
+            .replace(
+                r#""type": "VEC3" }"#,
+                r#""type": "VEC3" },
+            { "bufferView": 0, "componentType": 5123, "count": 4, "type": "SCALAR" }"#,
+            );
+        let report = read_broken(&with_indices);
+        assert_eq!(
+            report.errors[0].cause,
+            AssetErrorCause::Gltf("4 indices don't make whole triangles".to_string())
+        );
+    }
+
+    #[test]
+    fn test_attribute_count_mismatch_is_reported() {
+        let short_normals = TRIANGLE_GLTF.replace(
+            r#""byteOffset": 36, "componentType": 5126, "count": 3"#,
+            r#""byteOffset": 36, "componentType": 5126, "count": 2"#,
+        );
+        let report = read_broken(&short_normals);
+        assert_eq!(
+            report.errors[0].cause,
+            AssetErrorCause::Gltf("3 positions but 2 normals and 3 texcoords".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_normals_are_generated() {
+        let without_normals = TRIANGLE_GLTF.replace(r#", "NORMAL": 1"#, "");
+        let gltf = Gltf::from_slice(without_normals.as_bytes()).unwrap();
+        let buffers = load_buffers(&gltf, "triangle.gltf", &Vfs::new()).unwrap();
+        let mut report = LoadReport::new();
+        let primitives = read_primitives(&gltf, &buffers, "triangle.gltf", &mut report);
+
+        assert!(report.is_empty());
+        assert_eq!(primitives.len(), 1);
+        assert_eq!(primitives[0].indices, vec![0, 1, 2]);
+        assert_eq!(primitives[0].normals, [0.0, -1.0, 0.0].repeat(3));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::gamepad::GamepadState;
+use crate::vfs::Vfs;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use serde::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    FlyFast,
+    ToggleFly,
+    Respawn,
+    CycleCamera,
+    Pause,
+}
+
+pub const ALL_ACTIONS: [Action; 11] = [
+    Action::MoveForward,
+    Action::MoveBack,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Jump,
+    Action::Crouch,
+    Action::FlyFast,
+    Action::ToggleFly,
+    Action::Respawn,
+    Action::CycleCamera,
+    Action::Pause,
+];
+
+// Stored as a readable name in the config, e.g. "Space", "Mouse Left" or "Wheel Down"
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Binding {
+    Key(Keycode),
+    Mouse(MouseButton),
+    WheelUp,
+    WheelDown,
+}
+
+const MOUSE_BUTTON_NAMES: [(MouseButton, &str); 5] = [
+    (MouseButton::Left, "Mouse Left"),
+    (MouseButton::Right, "Mouse Right"),
+    (MouseButton::Middle, "Mouse Middle"),
+    (MouseButton::X1, "Mouse X1"),
+    (MouseButton::X2, "Mouse X2"),
+];
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Binding, String> {
+        match name.as_str() {
+            "Wheel Up" => return Ok(Binding::WheelUp),
+            "Wheel Down" => return Ok(Binding::WheelDown),
+            _ => {}
+        }
+
+        if let Some((button, _)) = MOUSE_BUTTON_NAMES.iter().find(|(_, n)| *n == name) {
+            return Ok(Binding::Mouse(*button));
+        }
+
+        Keycode::from_name(&name)
+            .map(Binding::Key)
+            .ok_or(format!("Unknown binding: {}", name))
+    }
+}
+
+impl Binding {
+    fn is_wheel(&self) -> bool {
+        matches!(self, Binding::WheelUp | Binding::WheelDown)
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> String {
+        match binding {
+            Binding::Key(key) => key.name(),
+            Binding::Mouse(button) => MOUSE_BUTTON_NAMES
+                .iter()
+                .find(|(b, _)| *b == button)
+                .map(|(_, name)| name.to_string())
+                .unwrap_or_else(|| "Mouse Unknown".to_string()),
+            Binding::WheelUp => "Wheel Up".to_string(),
+            Binding::WheelDown => "Wheel Down".to_string(),
+        }
+    }
+}
+
+// Any number of bindings can trigger the same action
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bindings {
+    actions: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let defaults = [
+            (Action::MoveForward, vec![Binding::Key(Keycode::W)]),
+            (Action::MoveBack, vec![Binding::Key(Keycode::S)]),
+            (Action::MoveLeft, vec![Binding::Key(Keycode::A)]),
+            (Action::MoveRight, vec![Binding::Key(Keycode::D)]),
+            (
+                Action::Jump,
+                vec![Binding::Key(Keycode::Space), Binding::WheelDown],
+            ),
+            (Action::Crouch, vec![Binding::Key(Keycode::LCtrl)]),
+            (Action::FlyFast, vec![Binding::Key(Keycode::LShift)]),
+            (Action::ToggleFly, vec![Binding::Key(Keycode::K)]),
+            (Action::Respawn, vec![Binding::Key(Keycode::R)]),
+            (Action::CycleCamera, vec![Binding::Key(Keycode::V)]),
+            (Action::Pause, vec![Binding::Key(Keycode::P)]),
+        ];
+
+        Bindings {
+            actions: defaults.iter().cloned().collect(),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn load(path: &str, vfs: &Vfs) -> Bindings {
+        let mut bindings = match vfs.read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!("Invalid bindings file {}, using defaults: {}", path, e);
+                Bindings::default()
+            }),
+            Err(_) => Bindings::default(),
+        };
+
+        // Actions added after the file was written keep their default bindings
+        for (action, defaults) in Bindings::default().actions {
+            bindings.actions.entry(action).or_insert(defaults);
+        }
+
+        bindings
+    }
+
+    // Written where they're read from, so that a mod's bindings file stays in the mod
+    pub fn save(&self, path: &str, vfs: &Vfs) {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        if let Err(e) = vfs.writable_path(path).and_then(|p| fs::write(p, json)) {
+            println!("Couldn't save bindings to {}: {}", path, e);
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    pub fn add(&mut self, action: Action, binding: Binding) {
+        let action_bindings = self.actions.entry(action).or_default();
+        if !action_bindings.contains(&binding) {
+            action_bindings.push(binding);
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.actions.insert(action, Vec::new());
+    }
+}
+
+// Tracks which bindings are active and maps them to actions
+pub struct Input {
+    bindings: Bindings,
+    held: Vec<Binding>,
+    pressed_this_frame: Vec<Binding>,
+    released_this_frame: Vec<Binding>,
+    pending_wheel: Vec<Binding>,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Input {
+        Input {
+            bindings,
+            held: Vec::new(),
+            pressed_this_frame: Vec::new(),
+            released_this_frame: Vec::new(),
+            pending_wheel: Vec::new(),
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Event::MouseWheel { y, .. } = *event {
+            if y > 0 {
+                self.pending_wheel.push(Binding::WheelUp);
+            } else if y < 0 {
+                self.pending_wheel.push(Binding::WheelDown);
+            }
+        }
+    }
+
+    // Wheel notches have no release, so they are pressed, held and released within the frame they arrive in.
+    // That way a notch on every frame is a new press each time.
+    pub fn tick(&mut self, keys: &[Keycode], mouse_buttons: &[MouseButton]) {
+        let mut held: Vec<Binding> = keys.iter().map(|key| Binding::Key(*key)).collect();
+        held.extend(mouse_buttons.iter().map(|button| Binding::Mouse(*button)));
+        let wheel = std::mem::take(&mut self.pending_wheel);
+
+        self.pressed_this_frame = held
+            .iter()
+            .filter(|binding| !self.held.contains(binding))
+            .chain(&wheel)
+            .cloned()
+            .collect();
+        self.released_this_frame = self
+            .held
+            .iter()
+            .filter(|binding| !binding.is_wheel() && !held.contains(binding))
+            .chain(&wheel)
+            .cloned()
+            .collect();
+        held.extend(wheel);
+        self.held = held;
+    }
+
+    pub fn get_action(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|b| self.held.contains(b))
+    }
+
+    pub fn get_action_down(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|b| self.pressed_this_frame.contains(b))
+    }
+
+    pub fn get_action_up(&self, action: Action) -> bool {
+        self.bindings
+            .get(action)
+            .iter()
+            .any(|b| self.released_this_frame.contains(b))
+    }
+
+    // Used when rebinding, to grab whatever the user presses next
+    pub fn first_pressed_binding(&self) -> Option<Binding> {
+        self.pressed_this_frame.first().cloned()
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    pub fn bindings_mut(&mut self) -> &mut Bindings {
+        &mut self.bindings
+    }
+}
+
+// Everything the player simulation reads in one tick, so that it can be recorded and replayed
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayerInput {
+    pub move_axis: (f32, f32), // x is right, y is forward, no longer than 1
+    pub look: (f32, f32),      // Yaw and pitch change in degrees
+    pub jump: bool,
+    pub jump_pressed: bool,
+    pub jump_released: bool,
+    pub crouch: bool,
+    pub fly_fast: bool,
+    pub toggle_fly: bool,
+    pub respawn: bool,
+}
+
+impl PlayerInput {
+    // Keys give a unit direction, the stick keeps its deflection so that a half tilt walks slower
+    pub fn from_devices(input: &Input, pad: &GamepadState, look: (f32, f32)) -> PlayerInput {
+        let key_x: f32 = {
+            if input.get_action(Action::MoveLeft) {
+                -1.0
+            } else if input.get_action(Action::MoveRight) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let key_y: f32 = {
+            if input.get_action(Action::MoveForward) {
+                1.0
+            } else if input.get_action(Action::MoveBack) {
+                -1.0
+            } else {
+                0.0
+            }
+        };
+        let key_length = (key_x * key_x + key_y * key_y).sqrt().max(1.0);
+
+        let mut move_x = key_x / key_length + pad.move_axis.0;
+        let mut move_y = key_y / key_length + pad.move_axis.1;
+        let move_length = (move_x * move_x + move_y * move_y).sqrt();
+        if move_length > 1.0 {
+            move_x /= move_length;
+            move_y /= move_length;
+        }
+
+        PlayerInput {
+            move_axis: (move_x, move_y),
+            look,
+            jump: input.get_action(Action::Jump) || pad.jump_held,
+            jump_pressed: input.get_action_down(Action::Jump) || pad.jump_pressed,
+            jump_released: input.get_action_up(Action::Jump) || pad.jump_released,
+            crouch: input.get_action(Action::Crouch) || pad.crouch_held,
+            fly_fast: input.get_action(Action::FlyFast),
+            toggle_fly: input.get_action_down(Action::ToggleFly),
+            respawn: input.get_action_down(Action::Respawn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binding_names() {
+        for binding in &[
+            Binding::Mouse(MouseButton::Left),
+            Binding::Mouse(MouseButton::X1),
+            Binding::WheelDown,
+        ] {
+            let name: String = (*binding).into();
+            assert_eq!(Binding::try_from(name), Ok(*binding));
+        }
+    }
+
+    #[test]
+    fn test_wheel_is_a_single_frame_press() {
+        let mut input = Input::new(Bindings::default());
+        input.pending_wheel.push(Binding::WheelDown);
+
+        input.tick(&[], &[]);
+        assert!(input.get_action_down(Action::Jump));
+        assert!(input.get_action(Action::Jump));
+        assert!(input.get_action_up(Action::Jump));
+
+        input.tick(&[], &[]);
+        assert!(!input.get_action_down(Action::Jump));
+        assert!(!input.get_action(Action::Jump));
+        assert!(!input.get_action_up(Action::Jump));
+    }
+
+    #[test]
+    fn test_wheel_on_consecutive_frames_presses_each_time() {
+        let mut input = Input::new(Bindings::default());
+        for _ in 0..3 {
+            input.pending_wheel.push(Binding::WheelDown);
+            input.tick(&[], &[]);
+            assert!(input.get_action_down(Action::Jump));
+        }
+    }
+}
@@ -42,8 +42,4 @@ impl Keys {
     pub fn get_key_down(&self, key: Keycode) -> bool {
         self.pressed_this_frame.contains(&key)
     }
-
-    pub fn get_key_up(&self, key: Keycode) -> bool {
-        self.released_this_frame.contains(&key)
-    }
 }
@@ -0,0 +1,102 @@
+use crate::asset_cache::AssetCache;
+use crate::asset_error::{AssetError, AssetErrorCause, LoadReport};
+use crate::assets;
+use crate::assets::{Prefab, Scene};
+use crate::vfs::Vfs;
+use serde::*;
+
+// One playable level, its name also names its records, ghosts and demos
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct LevelEntry {
+    pub name: String,
+    pub scene: String,
+    pub prefabs: String,
+}
+
+// The levels in play order, the first one is where the game starts by default
+#[derive(Debug, Deserialize)]
+pub struct LevelManifest {
+    level_entries: Vec<LevelEntry>,
+}
+
+impl LevelManifest {
+    pub fn load(path: &str, vfs: &Vfs) -> Result<LevelManifest, AssetError> {
+        let json_string = vfs
+            .read_to_string(path)
+            .map_err(|e| AssetError::from_io(path, &e))?;
+        serde_json::from_str(&json_string).map_err(|e| AssetError::from_json(path, &e))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LevelEntry> {
+        self.level_entries.iter().find(|l| l.name == name)
+    }
+
+    pub fn first(&self) -> Option<&LevelEntry> {
+        self.level_entries.first()
+    }
+
+    // Wraps around to the first level after the last one
+    pub fn after(&self, name: &str) -> Option<&LevelEntry> {
+        let index = self.level_entries.iter().position(|l| l.name == name)?;
+        self.level_entries
+            .get((index + 1) % self.level_entries.len())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.level_entries.iter().map(|l| l.name.as_str()).collect()
+    }
+}
+
+// The scene is parsed first, so that a broken scene fails before the current level is torn down.
+// Problems with the prefabs are only reported, their objects are left out of the world.
+pub fn load_level(
+    level: &LevelEntry,
+    manifest: &LevelManifest,
+    cache: &mut AssetCache,
+    report: &mut LoadReport,
+) -> Result<(Scene, Vec<Prefab>), AssetError> {
+    let scene = assets::load_scene(&level.scene, cache.vfs(), report)?;
+    if let Some(next_level) = scene.next_level() {
+        if manifest.get(next_level).is_none() {
+            report.push(AssetError::at_field(
+                &level.scene,
+                "next_level".to_string(),
+                AssetErrorCause::MissingLevel(next_level.to_string()),
+            ));
+        }
+    }
+
+    let prefabs = assets::load_prefabs(&level.prefabs, cache, report).unwrap_or_else(|e| {
+        report.push(e);
+        Vec::new()
+    });
+    Ok((scene, prefabs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_follow_each_other() {
+        let manifest: LevelManifest = serde_json::from_str(
+            r#"{ "level_entries": [
+                { "name": "a", "scene": "a.json", "prefabs": "prefabs.json" },
+                { "name": "b", "scene": "b.json", "prefabs": "prefabs.json" }
+            ] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.first().map(|l| l.name.as_str()), Some("a"));
+        assert_eq!(
+            manifest.after("a").map(|l| l.scene.as_str()),
+            Some("b.json")
+        );
+        assert_eq!(
+            manifest.after("b").map(|l| l.scene.as_str()),
+            Some("a.json")
+        );
+        assert_eq!(manifest.after("c"), None);
+        assert_eq!(manifest.names(), vec!["a", "b"]);
+    }
+}
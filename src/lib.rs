@@ -0,0 +1,6 @@
+// What the game and the cook binary share, so that both build the same cooked files and read the same mounts
+pub mod cooked;
+pub mod geom;
+pub mod math;
+pub mod mesh;
+pub mod vfs;
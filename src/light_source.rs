@@ -0,0 +1,11 @@
+use serde::*;
+
+pub const MAX_POINT_LIGHTS: usize = 10; // MAX_LIGHT_COUNT in triangle.glsl, the rest aren't rendered
+
+// A point light placed in the scene, the renderer builds its shadow cubemap from this
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PointLightSource {
+    pub position: [f32; 3],
+    pub intensity: f32,
+    pub attenuation: f32,
+}
@@ -0,0 +1,109 @@
+use crate::settings::LookSettings;
+use cgmath::*;
+
+const MAX_PITCH: f32 = 89.0; // Stops short of straight up/down, where yaw becomes meaningless
+
+// The view direction as angles in degrees. Yaw turns left around +y starting from -z,
+// pitch looks up.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ViewAngles {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl ViewAngles {
+    pub fn new(yaw: f32, pitch: f32) -> ViewAngles {
+        let mut angles = ViewAngles { yaw, pitch };
+        angles.normalize();
+        angles
+    }
+
+    pub fn from_forward(forward: Vector3<f32>) -> ViewAngles {
+        let horz_length = (forward.x * forward.x + forward.z * forward.z).sqrt();
+        let yaw = Rad((-forward.x).atan2(-forward.z));
+        let pitch = Rad(forward.y.atan2(horz_length));
+        ViewAngles::new(Deg::from(yaw).0, Deg::from(pitch).0)
+    }
+
+    // Takes (yaw, pitch) deltas in degrees
+    pub fn rotate(&mut self, delta: (f32, f32)) {
+        self.yaw += delta.0;
+        self.pitch += delta.1;
+        self.normalize();
+    }
+
+    fn normalize(&mut self) {
+        self.yaw = self.yaw.rem_euclid(360.0);
+        self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        let (yaw_sin, yaw_cos) = Rad::from(Deg(self.yaw)).sin_cos();
+        let (pitch_sin, pitch_cos) = Rad::from(Deg(self.pitch)).sin_cos();
+        Vector3::new(-yaw_sin * pitch_cos, pitch_sin, -yaw_cos * pitch_cos)
+    }
+
+    // World to view rotation, with an optional roll around the view direction
+    pub fn view_rotation(&self, roll: f32) -> Matrix4<f32> {
+        Matrix4::from_angle_z(Deg(roll))
+            * Matrix4::from_angle_x(Deg(-self.pitch))
+            * Matrix4::from_angle_y(Deg(-self.yaw))
+    }
+
+    pub fn view_matrix(&self, eye: Point3<f32>, roll: f32) -> Matrix4<f32> {
+        self.view_rotation(roll) * Matrix4::from_translation(-eye.to_vec())
+    }
+}
+
+// Relative mouse motion in counts to (yaw, pitch) deltas in degrees
+pub fn mouse_to_look_delta(mouse: (f32, f32), settings: &LookSettings, dt: f32) -> (f32, f32) {
+    let (mouse_x, mouse_y) = mouse;
+
+    // Faster flicks turn further per count, like the usual "m_accel"
+    let mut sensitivity = settings.sensitivity;
+    if settings.acceleration > 0.0 && dt > 0.0 {
+        let counts_per_ms = (mouse_x * mouse_x + mouse_y * mouse_y).sqrt() / dt;
+        sensitivity *= 1.0 + settings.acceleration * counts_per_ms;
+    }
+
+    let y_sign = if settings.invert_y { 1.0 } else { -1.0 };
+    (-mouse_x * sensitivity, mouse_y * sensitivity * y_sign)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_round_trip() {
+        let angles = ViewAngles::new(135.0, -30.0);
+        let round_trip = ViewAngles::from_forward(angles.forward());
+        assert!((round_trip.yaw - angles.yaw).abs() < 0.001);
+        assert!((round_trip.pitch - angles.pitch).abs() < 0.001);
+
+        let forward = ViewAngles::default().forward();
+        assert!((forward - -Vector3::unit_z()).magnitude() < 0.0001);
+    }
+
+    #[test]
+    fn test_pitch_is_clamped() {
+        let mut angles = ViewAngles::new(350.0, 80.0);
+        angles.rotate((20.0, 30.0));
+        assert!((angles.yaw - 10.0).abs() < 0.001);
+        assert_eq!(angles.pitch, MAX_PITCH);
+    }
+
+    #[test]
+    fn test_view_matrix_matches_look_at() {
+        let angles = ViewAngles::new(40.0, 20.0);
+        let eye = Point3::new(1.0, 2.0, 3.0);
+        let look_at = Matrix4::look_at_dir(eye, angles.forward(), Vector3::unit_y());
+        let view = angles.view_matrix(eye, 0.0);
+        for (a, b) in AsRef::<[f32; 16]>::as_ref(&look_at)
+            .iter()
+            .zip(AsRef::<[f32; 16]>::as_ref(&view).iter())
+        {
+            assert!((a - b).abs() < 0.0001);
+        }
+    }
+}
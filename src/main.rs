@@ -8,17 +8,39 @@ extern crate serde_json;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use std::path::{Path, PathBuf};
 
+use hell::{cooked, geom, mesh, vfs};
+
+mod asset_cache;
+mod asset_error;
 mod assets;
-mod geom;
+mod bindings_menu;
+mod camera;
+mod demo;
+mod editor;
+mod file_watcher;
+mod gamepad;
+mod ghost;
+mod gltf_import;
+mod input;
 mod keys;
-mod math;
-mod mesh;
+mod level;
+mod light_source;
+mod look;
 mod physics;
 mod player;
+mod player_state;
 mod render;
+mod run_timer;
+mod settings;
+mod spawn_point;
 mod static_object;
 mod time;
+mod trigger;
+mod water_volume;
+mod world;
 
 // the problem is that we instantiate the meshes here, and the instance is dropped at the
 // end of the for loop. the problem is that, the static_objects keep references to those meshes.
@@ -34,54 +56,501 @@ mod time;
 // staticobject -> ctor(&prefab, transform)
 // rendering: calling the same draw call, with the same vbo, but different uniforms
 
+const NEXT_LEVEL_DELAY: f32 = 2000.0; // In milliseconds, long enough to see the final time
+
 fn main() {
+    let vfs = match vfs::Vfs::mount_game_data() {
+        Ok(vfs) => vfs,
+        Err(e) => {
+            println!("Unable to find the game data: {}", e);
+            return;
+        }
+    };
+    println!("Mounted {}", vfs);
+
+    // The level to start from can be given by name, e.g. `hell parkour`
+    let manifest_path = "assets/levels.json";
+    let manifest = match level::LevelManifest::load(manifest_path, &vfs) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            println!("Unable to load the level list: {}", e);
+            return;
+        }
+    };
+    let start_level = match std::env::args().nth(1) {
+        Some(name) => manifest.get(&name),
+        None => manifest.first(),
+    };
+    let mut level = match start_level {
+        Some(level) => level.clone(),
+        None => {
+            println!(
+                "Unknown level, the levels are: {}",
+                manifest.names().join(", ")
+            );
+            return;
+        }
+    };
+
     let sdl_context = sdl2::init().unwrap();
+    let settings = settings::Settings::load("settings.json", &vfs);
 
-    let mut renderer = render::renderer::Renderer::init(&sdl_context);
-    let mut ui = render::ui::Ui::init();
+    // Warping the cursor instead of reading raw motion keeps the OS pointer acceleration
+    let relative_mode_warp = if settings.look.raw_input { "0" } else { "1" };
+    sdl2::hint::set("SDL_MOUSE_RELATIVE_MODE_WARP", relative_mode_warp);
+
+    let mut asset_cache = asset_cache::AssetCache::new(vfs);
+    let mut renderer = render::renderer::Renderer::init(&sdl_context, &mut asset_cache);
+    let mut ui = render::ui::Ui::init(&mut asset_cache);
     let mut time = time::Time::new(&sdl_context);
     let mut event_pump = sdl_context.event_pump().unwrap();
     let mut keys = keys::Keys::new();
-    let mut player = player::Player::new();
+    let bindings_path = "bindings.json";
+    let mut input = input::Input::new(input::Bindings::load(bindings_path, asset_cache.vfs()));
+    let mut bindings_menu = bindings_menu::BindingsMenu::new(bindings_path);
+    let mut gamepad = gamepad::Gamepad::new(&sdl_context, settings.gamepad.clone());
 
-    let prefabs = assets::load_prefabs("assets/prefabs.json");
-    let static_objects = assets::create_static_objects("assets/scene.json", &prefabs);
+    let mut load_report = asset_error::LoadReport::new();
+    let loaded_level = level::load_level(&level, &manifest, &mut asset_cache, &mut load_report);
+    if !load_report.is_empty() {
+        println!("{}", load_report);
+    }
+    let (scene, mut prefabs) = match loaded_level {
+        Ok(loaded_level) => loaded_level,
+        Err(e) => {
+            println!("Unable to load the scene: {}", e);
+            return;
+        }
+    };
+    let mut world = assets::build_world(scene, &level.scene, &prefabs, &mut load_report);
+    println!("Assets in use: {}", asset_cache.memory_report());
+    unsafe {
+        renderer.set_point_lights(&world.point_lights);
+    }
+    let mut player = player::Player::new(world.spawn_point, &settings.movement);
+    let mut camera = camera::Camera::new(settings.camera.clone());
+    let mut editor = editor::Editor::new();
+    let mut is_simulation_paused = false;
+    let mut run_timer = run_timer::RunTimer::for_level(&level.name, asset_cache.vfs());
+    let mut ghost_recorder = ghost::GhostRecorder::new();
+    let mut best_ghost = ghost::GhostRun::load(&ghost::ghost_path(&level.name), asset_cache.vfs());
+    let mut player_event_log = player_state::PlayerEventLog::new(
+        Some(Path::new(run_timer::RECORDS_DIR).join("player_events.log"))
+            .filter(|_| settings.log_player_events)
+            .and_then(|path| asset_cache.vfs().writable_path(path).ok())
+            .as_deref(),
+    );
+    let mut demo_recording: Option<demo::Demo> = None;
+    let mut demo_playback: Option<demo::DemoPlayer> = None;
+    let mut asset_watcher = create_asset_watcher(&settings, &level, &prefabs, asset_cache.vfs());
+    let mut pending_asset_changes: Vec<PathBuf> = Vec::new();
+    let mut pending_level: Option<(level::LevelEntry, f32)> = None; // With the time left until the switch
 
     'main: loop {
         let (mut mouse_x, mut mouse_y) = (0.0, 0.0);
+        let mut is_mouse_clicked = false;
 
         let dt = time.tick();
 
+        let changed_files = if settings.hot_reload.enabled {
+            asset_watcher.poll(dt, asset_cache.vfs())
+        } else {
+            Vec::new()
+        };
+        let (changed_shaders, changed_assets): (Vec<PathBuf>, Vec<PathBuf>) = changed_files
+            .into_iter()
+            .partition(|f| f.extension() == Some("glsl".as_ref()));
+        if !changed_shaders.is_empty() {
+            let shader_reloads = asset_cache.reload_shaders(&changed_shaders);
+            for (path, result) in &shader_reloads {
+                match result {
+                    Ok(()) => println!("Reloaded shader {}", path.display()),
+                    Err(log) => println!("Shader {} failed to compile:\n{}", path.display(), log),
+                }
+            }
+            ui.show_shader_reloads(&shader_reloads);
+        }
+        if !changed_assets.is_empty() {
+            pending_asset_changes.extend(changed_assets);
+            let mut reload_report = asset_error::LoadReport::new();
+            // A half-saved scene leaves everything as it is, the changes are retried on the next save
+            match assets::load_scene(&level.scene, asset_cache.vfs(), &mut reload_report) {
+                Ok(scene) => {
+                    if let Err(e) = assets::reload_prefabs(
+                        &mut prefabs,
+                        &level.prefabs,
+                        &pending_asset_changes,
+                        &mut asset_cache,
+                        &mut reload_report,
+                    ) {
+                        reload_report.push(e);
+                    }
+                    world = assets::build_world(scene, &level.scene, &prefabs, &mut reload_report);
+                    unsafe {
+                        renderer.set_point_lights(&world.point_lights);
+                    }
+                    // The player stays where they are, without re-entering the triggers around them
+                    world.update_triggers(player.position());
+                    watch_assets(&mut asset_watcher, &[], &prefabs, asset_cache.vfs());
+
+                    println!("Reloaded {} changed asset(s)", pending_asset_changes.len());
+                    println!("Assets in use: {}", asset_cache.memory_report());
+                    if !reload_report.is_empty() {
+                        println!("{}", reload_report);
+                    }
+                    pending_asset_changes.clear();
+                }
+                Err(e) => println!("Hot reload skipped: {}", e),
+            }
+        }
+
+        let due_level = match pending_level.take() {
+            Some((next_level, delay)) if delay <= dt => Some(next_level),
+            Some((next_level, delay)) => {
+                pending_level = Some((next_level, delay - dt));
+                None
+            }
+            None => None,
+        };
+        if let Some(next_level) = due_level {
+            let mut level_report = asset_error::LoadReport::new();
+            match level::load_level(&next_level, &manifest, &mut asset_cache, &mut level_report) {
+                Ok((scene, next_prefabs)) => {
+                    // Dropping the old prefabs frees their meshes and textures on the GPU
+                    prefabs = next_prefabs;
+                    world =
+                        assets::build_world(scene, &next_level.scene, &prefabs, &mut level_report);
+                    unsafe {
+                        renderer.set_point_lights(&world.point_lights);
+                    }
+                    level = next_level;
+
+                    player.reset(world.spawn_point, player.is_flying());
+                    run_timer = run_timer::RunTimer::for_level(&level.name, asset_cache.vfs());
+                    ghost_recorder = ghost::GhostRecorder::new();
+                    best_ghost =
+                        ghost::GhostRun::load(&ghost::ghost_path(&level.name), asset_cache.vfs());
+                    demo_recording = None;
+                    demo_playback = None;
+                    // The undo history belongs to the old level
+                    editor = editor::Editor::new();
+                    asset_watcher =
+                        create_asset_watcher(&settings, &level, &prefabs, asset_cache.vfs());
+                    pending_asset_changes.clear();
+                    println!("Loaded level {}", level.name);
+                    println!("Assets in use: {}", asset_cache.memory_report());
+                }
+                Err(e) => println!("Unable to load level {}: {}", next_level.name, e),
+            }
+            if !level_report.is_empty() {
+                println!("{}", level_report);
+            }
+        }
+
         for event in event_pump.poll_iter() {
+            gamepad.handle_event(&event);
+            input.handle_event(&event);
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => break 'main,
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
+                    repeat: false,
+                    ..
+                } if !bindings_menu.is_open() && !editor.is_open() => break 'main,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
                     ..
-                } => break 'main,
+                } => is_mouse_clicked = true,
+                // Several motion events can arrive in one frame, the look uses all of them
                 Event::MouseMotion { xrel, yrel, .. } => {
-                    mouse_x = xrel as f32;
-                    mouse_y = yrel as f32;
+                    mouse_x += xrel as f32;
+                    mouse_y += yrel as f32;
                 }
                 _ => {}
             }
         }
 
-        keys.tick(
-            event_pump
-                .keyboard_state()
-                .pressed_scancodes()
-                .filter_map(Keycode::from_scancode)
-                .collect(),
-        );
+        let pressed_keys: Vec<Keycode> = event_pump
+            .keyboard_state()
+            .pressed_scancodes()
+            .filter_map(Keycode::from_scancode)
+            .collect();
+        let pressed_mouse_buttons: Vec<MouseButton> =
+            event_pump.mouse_state().pressed_mouse_buttons().collect();
+        input.tick(&pressed_keys, &pressed_mouse_buttons);
+        keys.tick(pressed_keys);
+        gamepad.tick();
 
-        player.tick(&keys, (mouse_x, mouse_y), &static_objects, dt);
+        // The menu reads raw keys, so that it stays usable no matter what is bound
+        if keys.get_key_down(Keycode::F1) && !editor.is_open() {
+            bindings_menu.toggle();
+        } else if bindings_menu.is_open() {
+            bindings_menu.tick(&keys, &mut input, asset_cache.vfs());
+        } else if keys.get_key_down(Keycode::F2)
+            || (editor.is_open() && keys.get_key_down(Keycode::Escape))
+        {
+            editor.toggle(&mut camera, &world.objects);
+        } else if editor.is_open() {
+            let drag =
+                Some(mouse_x).filter(|_| pressed_mouse_buttons.contains(&MouseButton::Right));
+            editor.tick(&keys, is_mouse_clicked, drag, &camera, &mut world, &prefabs);
+        } else {
+            if input.get_action_down(input::Action::CycleCamera) {
+                camera.cycle_mode();
+            }
+            if input.get_action_down(input::Action::Pause) {
+                is_simulation_paused = !is_simulation_paused;
+            }
+            if keys.get_key_down(Keycode::F3) {
+                pending_level = manifest.after(&level.name).map(|l| (l.clone(), 0.0));
+            }
+        }
+        if bindings_menu.is_open() || editor.is_open() {
+            // The menus don't need recording and playback controls
+        } else if keys.get_key_down(Keycode::F5) {
+            match demo_recording.take() {
+                Some(demo) => demo.save(&demo::demo_path(&level.name), asset_cache.vfs()),
+                None => {
+                    let start = demo::DemoStart {
+                        spawn_point: world.spawn_point,
+                        is_flying: player.is_flying(),
+                    };
+                    restart_level(&mut player, &mut world, &mut run_timer, start);
+                    demo_playback = None;
+                    demo_recording = Some(demo::Demo::new(&level.name, start));
+                }
+            }
+        } else if keys.get_key_down(Keycode::F6) {
+            if demo_playback.take().is_none() {
+                if let Some(demo) =
+                    demo::Demo::load(&demo::demo_path(&level.name), asset_cache.vfs())
+                {
+                    restart_level(&mut player, &mut world, &mut run_timer, demo.start);
+                    demo_recording = None;
+                    demo_playback = Some(demo::DemoPlayer::new(demo));
+                }
+            }
+        } else if let Some(playback) = demo_playback.as_mut() {
+            if keys.get_key_down(Keycode::LeftBracket) {
+                playback.slower();
+            } else if keys.get_key_down(Keycode::RightBracket) {
+                playback.faster();
+            }
+        }
+        if !bindings_menu.is_open() && keys.get_key_down(Keycode::F9) {
+            match assets::save_world(&world, &level.scene, asset_cache.vfs()) {
+                Ok(()) => println!("Saved the scene to {}", level.scene),
+                Err(e) => println!("Unable to save the scene: {}", e),
+            }
+        }
+
+        let device_input = {
+            if bindings_menu.is_open() {
+                input::PlayerInput::default()
+            } else {
+                {
+                    // The right stick turns the view just like the mouse does
+                    let mouse_look = if editor.is_dragging() {
+                        (0.0, 0.0)
+                    } else {
+                        look::mouse_to_look_delta((mouse_x, mouse_y), &settings.look, dt)
+                    };
+                    let pad_look = gamepad.look_delta(dt);
+                    let look = (mouse_look.0 + pad_look.0, mouse_look.1 + pad_look.1);
+                    input::PlayerInput::from_devices(&input, &gamepad.state(), look)
+                }
+            }
+        };
+
+        // Live play runs one tick per frame, playback runs as many recorded ticks as fit in dt
+        let ticks: Vec<demo::DemoFrame> = {
+            if is_simulation_paused || editor.is_open() {
+                Vec::new()
+            } else if let Some(playback) = demo_playback.as_mut() {
+                playback.advance(dt)
+            } else if camera.controls_player() {
+                vec![demo::DemoFrame {
+                    dt,
+                    input: device_input,
+                }]
+            } else {
+                vec![demo::DemoFrame {
+                    dt,
+                    input: input::PlayerInput::default(),
+                }]
+            }
+        };
+        if let Some(playback) = &demo_playback {
+            if playback.is_finished() {
+                demo_playback = None;
+            }
+        }
+
+        let mut player_events = Vec::new();
+        for tick in ticks {
+            let dt = tick.dt;
+            if let Some(demo) = demo_recording.as_mut() {
+                demo.record(tick.input, dt);
+            }
+
+            player.tick(&tick.input, &world, dt);
+
+            run_timer.tick(dt);
+            for (trigger_index, trigger_event) in world.update_triggers(player.position()) {
+                let mut teleport_arrival = None;
+                let trigger = &world.triggers[trigger_index];
+                match (&trigger.kind, trigger_event) {
+                    (trigger::TriggerKind::Start, trigger::TriggerEvent::Entered) => {
+                        // A fresh run starts from the level spawn, not from an old checkpoint
+                        player.set_respawn_point(world.spawn_point);
+                    }
+                    (
+                        trigger::TriggerKind::Checkpoint(spawn_point),
+                        trigger::TriggerEvent::Entered,
+                    ) => {
+                        player.set_respawn_point(*spawn_point);
+                    }
+                    (trigger::TriggerKind::Launch(launch), trigger::TriggerEvent::Entered) => {
+                        player.launch(launch, &world);
+                    }
+                    (trigger::TriggerKind::Boost(multiplier), trigger::TriggerEvent::Entered) => {
+                        player.boost(*multiplier);
+                    }
+                    (
+                        trigger::TriggerKind::Teleport(teleporter),
+                        trigger::TriggerEvent::Entered,
+                    ) => {
+                        let destination = teleporter
+                            .destination
+                            .as_ref()
+                            .and_then(|name| world.trigger_index(name));
+                        if let Some(destination) = destination {
+                            if let trigger::TriggerKind::Teleport(arrival) =
+                                &world.triggers[destination].kind
+                            {
+                                player.teleport(&arrival.exit, teleporter.momentum);
+                                teleport_arrival = Some(destination);
+                                ui.flash();
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                match run_timer.on_trigger(
+                    &trigger.kind,
+                    &trigger.name,
+                    trigger_event,
+                    asset_cache.vfs(),
+                ) {
+                    Some(run_timer::RunEvent::Started) => ghost_recorder.start(),
+                    Some(run_timer::RunEvent::Finished { is_new_best }) => {
+                        let ghost_run = ghost_recorder.finish();
+                        if is_new_best {
+                            ghost_run.save(&ghost::ghost_path(&level.name), asset_cache.vfs());
+                            best_ghost = Some(ghost_run);
+                        }
+                        if let Some(next_level) = &world.next_level {
+                            pending_level = manifest
+                                .get(next_level)
+                                .map(|l| (l.clone(), NEXT_LEVEL_DELAY));
+                        }
+                    }
+                    None => {}
+                }
 
+                if let Some(destination) = teleport_arrival {
+                    world.triggers[destination].mark_entered();
+                }
+            }
+
+            let tick_events = player.take_events();
+            player_event_log.tick(dt);
+            player_event_log.record(&tick_events);
+            player_events.extend(tick_events);
+            ghost_recorder.record(run_timer.elapsed(), player.position(), player.forward());
+        }
+
+        camera.tick(&player, &player_events, &world, &device_input, dt);
+
+        let player_pose = match camera.mode() {
+            camera::CameraMode::FirstPerson => None,
+            _ => Some((player.position(), player.forward())),
+        };
+        let ghost_pose = match run_timer.state() {
+            run_timer::RunState::Running => best_ghost
+                .as_ref()
+                .and_then(|ghost_run| ghost_run.sample(run_timer.elapsed())),
+            _ => None,
+        };
+
+        renderer.set_selection(editor.selection(&world));
         unsafe {
-            renderer.render(&static_objects, player.get_view_matrix());
-            ui.draw(&player);
+            renderer.render(
+                &world.static_objects,
+                camera.get_view_matrix(),
+                camera.fov(),
+                world.water_at(camera.position()).is_some(),
+                ghost_pose,
+                player_pose,
+            );
+            let demo_status = match (&demo_recording, &demo_playback) {
+                (Some(_), _) => Some("REC".to_string()),
+                (_, Some(playback)) => Some(format!("DEMO {}x", playback.speed())),
+                _ => None,
+            };
+            ui.tick(dt);
+            ui.draw(
+                &player,
+                &run_timer,
+                &bindings_menu,
+                input.bindings(),
+                demo_status.as_deref(),
+                &editor.status_lines(&world, &prefabs),
+            );
         }
 
         renderer.finish_render();
     }
 }
+
+// Starts a fresh attempt, so that a demo and its playback begin from the same state
+fn restart_level(
+    player: &mut player::Player,
+    world: &mut world::World,
+    run_timer: &mut run_timer::RunTimer,
+    start: demo::DemoStart,
+) {
+    player.reset(start.spawn_point, start.is_flying);
+    world.reset_triggers();
+    run_timer.reset();
+}
+
+// Watches the level's own files and the shaders
+fn create_asset_watcher(
+    settings: &settings::Settings,
+    level: &level::LevelEntry,
+    prefabs: &[assets::Prefab],
+    vfs: &vfs::Vfs,
+) -> file_watcher::FileWatcher {
+    let mut watcher = file_watcher::FileWatcher::new(settings.hot_reload.poll_interval);
+    watch_assets(&mut watcher, &[&level.prefabs, &level.scene], prefabs, vfs);
+    for shader_file in vfs.read_dir("src/shaders") {
+        watcher.watch(&shader_file, vfs);
+    }
+    watcher
+}
+
+fn watch_assets(
+    watcher: &mut file_watcher::FileWatcher,
+    files: &[&str],
+    prefabs: &[assets::Prefab],
+    vfs: &vfs::Vfs,
+) {
+    for file in files {
+        watcher.watch(Path::new(file), vfs);
+    }
+    for dependency in prefabs.iter().flat_map(|p| p.dependencies()) {
+        watcher.watch(dependency, vfs);
+    }
+}
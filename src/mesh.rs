@@ -1,23 +1,30 @@
 extern crate tobj;
 use crate::geom::Triangle;
 use crate::math::*;
+use crate::vfs::Vfs;
 use cgmath::*;
+use std::path::{Path, PathBuf};
 
 pub struct Mesh {
     pub triangles: Vec<Triangle>,
 }
 
 pub fn read_vertex_array(mesh: &tobj::Mesh) -> (Vec<f32>, Vec<u32>) {
-    let vertices = mesh.positions.clone();
-    let texcoords = mesh.texcoords.clone();
-    let normals = mesh.normals.clone();
+    let vertex_data = interleave_vertices(&mesh.positions, &mesh.texcoords, &mesh.normals);
+    let index_data = mesh.indices.clone();
+
+    (vertex_data, index_data)
+}
+
+// Flat position, texcoord and normal arrays into the layout of material::create_vertex_buffers
+pub fn interleave_vertices(vertices: &[f32], texcoords: &[f32], normals: &[f32]) -> Vec<f32> {
     let iter_zip = vertices
         .chunks(3)
         .zip(texcoords.chunks(2))
         .zip(normals.chunks(3));
 
     // TODO: Shouldn't take the texcoord into consideration if there isn't a texture
-    let vertex_data = iter_zip
+    iter_zip
         .map(|vec_tuple| {
             // (([v, v, v], [tx, tx]), [n, n, n])
             vec![
@@ -32,17 +39,65 @@ pub fn read_vertex_array(mesh: &tobj::Mesh) -> (Vec<f32>, Vec<u32>) {
             ]
         })
         .flatten()
-        .collect::<Vec<f32>>();
-    let index_data = mesh.indices.clone();
+        .collect::<Vec<f32>>()
+}
 
-    (vertex_data, index_data)
+// Material libraries are read through the vfs as well, relative to the obj
+pub fn load_obj(obj_path: &str, vfs: &Vfs) -> tobj::LoadResult {
+    let obj_bytes = vfs
+        .read(obj_path)
+        .map_err(|_| tobj::LoadError::OpenFileFailed)?;
+    let obj_dir = Path::new(obj_path)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    tobj::load_obj_buf(&mut obj_bytes.as_slice(), |mtl_path| {
+        let mtl_bytes = vfs
+            .read(obj_dir.join(mtl_path))
+            .map_err(|_| tobj::LoadError::OpenFileFailed)?;
+        tobj::load_mtl_buf(&mut mtl_bytes.as_slice())
+    })
+}
+
+// The obj itself and its material libraries, which are relative to the obj
+pub fn obj_dependencies(obj_path: &str, vfs: &Vfs) -> Vec<PathBuf> {
+    let obj_path = Path::new(obj_path);
+    let mut dependencies = vec![obj_path.to_path_buf()];
+    if let Ok(obj_text) = vfs.read_to_string(obj_path) {
+        let obj_dir = obj_path.parent().unwrap_or_else(|| Path::new(""));
+        for line in obj_text.lines() {
+            if let Some(mtl_name) = line.strip_prefix("mtllib ") {
+                dependencies.push(obj_dir.join(mtl_name.trim()));
+            }
+        }
+    }
+    dependencies
+}
+
+pub fn diffuse_texture_path(tobj_mat: &tobj::Material) -> Option<String> {
+    if tobj_mat.diffuse_texture.is_empty() {
+        None
+    } else {
+        Some(format!("assets/{}", tobj_mat.diffuse_texture))
+    }
+}
+
+// Tints the white texture that stands in for a missing diffuse texture
+pub fn diffuse_tint(tobj_mat: &tobj::Material) -> [f32; 4] {
+    [
+        tobj_mat.diffuse[0],
+        tobj_mat.diffuse[1],
+        tobj_mat.diffuse[2],
+        1.0,
+    ]
 }
 
 impl Mesh {
     pub fn new(mesh: &tobj::Mesh) -> Mesh {
-        let indices = mesh.indices.clone();
-        let vertices = mesh
-            .positions
+        Mesh::from_indexed(&mesh.positions, &mesh.indices)
+    }
+
+    pub fn from_indexed(positions: &[f32], indices: &[u32]) -> Mesh {
+        let vertices = positions
             .chunks(3)
             .map(|slice| {
                 Point3::new(
@@ -6,6 +6,13 @@ use std::cmp::Ordering;
 const PLAYER_HEIGHT: f32 = 1.0;
 const PLAYER_CAPSULE_RADIUS: f32 = 0.5;
 
+// A triangle of a static object that pushed the player capsule out
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub object_index: usize,
+    pub normal: Vector3<f32>,
+}
+
 #[derive(Clone, Copy, Debug)]
 struct PlayerShape {
     capsule0: Point3<f32>,
@@ -64,10 +71,11 @@ impl std::fmt::Display for PlayerShape {
 pub fn resolve_penetration(
     static_objects: &Vec<StaticObject>,
     player_pos: Point3<f32>,
-) -> Vector3<f32> {
+) -> (Vector3<f32>, Vec<Contact>) {
     let mut player_shape = PlayerShape::new(player_pos, PLAYER_HEIGHT, PLAYER_CAPSULE_RADIUS);
     let mut total_displacement = Vector3::zero();
-    for obj in static_objects {
+    let mut contacts = Vec::new();
+    for (object_index, obj) in static_objects.iter().enumerate() {
         // TODO #PERF: We can do this multithreaded
         // Technically, there _is_ an order which _might_ change the outcome of the calculation
         // But we don't rely on that. We might as well send each triangle to a different thread
@@ -88,11 +96,60 @@ pub fn resolve_penetration(
                 player_shape.displace(penet);
 
                 total_displacement += penet;
+                if penet.magnitude2() > 0.0 {
+                    contacts.push(Contact {
+                        object_index,
+                        normal: penet.normalize(),
+                    });
+                }
             }
         }
     }
 
-    total_displacement
+    (total_displacement, contacts)
+}
+
+pub fn capsule_overlaps_aabb(player_pos: Point3<f32>, aabb: Aabb) -> bool {
+    let player_shape = PlayerShape::new(player_pos, PLAYER_HEIGHT, PLAYER_CAPSULE_RADIUS);
+
+    // The capsule is always upright, so the horizontal and vertical distances can be
+    // computed separately: horizontal one from the center, vertical one from the segment
+    let dx = (aabb.min.x - player_pos.x)
+        .max(player_pos.x - aabb.max.x)
+        .max(0.0);
+    let dz = (aabb.min.z - player_pos.z)
+        .max(player_pos.z - aabb.max.z)
+        .max(0.0);
+    let dy = (aabb.min.y - player_shape.capsule0.y)
+        .max(player_shape.capsule1.y - aabb.max.y)
+        .max(0.0);
+
+    dx * dx + dy * dy + dz * dz <= player_shape.radius * player_shape.radius
+}
+
+// Closest hit along the ray within max_distance, as (distance, object index)
+pub fn raycast(
+    static_objects: &[StaticObject],
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+) -> Option<(f32, usize)> {
+    let mut closest: Option<(f32, usize)> = None;
+    for (object_index, obj) in static_objects.iter().enumerate() {
+        for tri in &obj.triangles {
+            if let Some(t) = ray_triangle_check(origin, direction, *tri) {
+                let is_closer = match closest {
+                    Some((closest_t, _)) => t < closest_t,
+                    None => true,
+                };
+                if t <= max_distance && is_closer {
+                    closest = Some((t, object_index));
+                }
+            }
+        }
+    }
+
+    closest
 }
 
 pub fn grounded_check(
@@ -423,6 +480,18 @@ mod tests {
         assert_eq!(compute_penetration(player_shape, tri), None);
     }
 
+    #[test]
+    fn test_capsule_overlaps_aabb() {
+        let aabb = Aabb::new(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0));
+
+        assert!(capsule_overlaps_aabb(Point3::new(1.0, 1.0, 1.0), aabb));
+        assert!(capsule_overlaps_aabb(Point3::new(-0.4, 1.0, 1.0), aabb));
+        assert!(capsule_overlaps_aabb(Point3::new(1.0, 2.9, 1.0), aabb));
+        assert!(!capsule_overlaps_aabb(Point3::new(-0.6, 1.0, 1.0), aabb));
+        assert!(!capsule_overlaps_aabb(Point3::new(1.0, -1.1, 1.0), aabb));
+        assert!(!capsule_overlaps_aabb(Point3::new(-0.4, 2.9, -0.4), aabb));
+    }
+
     fn setup_player_shape_at_zero() -> PlayerShape {
         PlayerShape::new(
             Point3::new(0.0, 0.0, 0.0),
@@ -1,11 +1,14 @@
 use crate::geom::*;
-use crate::keys::Keys;
-use crate::static_object::StaticObject;
+use crate::input::PlayerInput;
+use crate::look::ViewAngles;
 use crate::physics::*;
+use crate::player_state::{MovementState, PlayerEvent};
+use crate::settings::MovementSettings;
+use crate::spawn_point::SpawnPoint;
+use crate::trigger::{Launch, TeleportMomentum};
+use crate::world::World;
 use cgmath::*;
-use sdl2::keyboard::Keycode;
 
-const SENSITIVITY: f32 = 0.004;
 const GROUND_ACCELERATION: f32 = 0.3;
 const GROUND_FRICTION: f32 = 0.02;
 const GROUND_FRICTION_LOWER_LIMIT: f32 = 0.001; // Stop if the speed is lower than this
@@ -14,79 +17,179 @@ const AIR_DECELERATION: f32 = 0.00005;
 const MAX_SPEED_ON_ONE_DIMENSION: f32 = 0.01;
 const GRAVITY: f32 = 0.00003;
 const JUMP_FORCE: f32 = 0.01;
-const START_POSITION: Point3<f32> = Point3::new(-5.0, 2.0, 5.0);
+const SWIM_ACCELERATION: f32 = 0.2;
+const SWIM_MAX_SPEED: f32 = 0.005;
+const WATER_DRAG: f32 = 0.004;
+const WATER_BUOYANCY: f32 = GRAVITY * 0.9; // Slightly less than gravity, so idle players slowly sink
+const WATER_SURFACE_OFFSET: f32 = 0.3; // How far below the surface the player floats
+const WATER_JUMP_FORCE: f32 = 0.01;
+const CLIMB_SPEED: f32 = 0.005;
+const CLIMB_LOOK_DOWN_THRESHOLD: f32 = -0.4; // Looking further down than this climbs down
+const CLIMB_STICK_SPEED: f32 = 0.001; // Keeps the capsule touching the ladder
+const CLIMB_JUMP_OFF_SPEED: f32 = 0.006;
+const CROUCH_SPEED_SCALE: f32 = 0.5;
+const LAUNCH_GROUND_IGNORE_TIME: f32 = 100.0; // Milliseconds the launch pad isn't treated as ground
 
 pub struct Player {
     pub velocity: Vector3<f32>,
     position: Point3<f32>,
-    forward: Vector3<f32>,
-    prev_is_grounded: bool,
+    view: ViewAngles,
+    state: MovementState,
+    events: Vec<PlayerEvent>,
     gonna_jump: bool,
-    enabled_fly_move: bool,
+    ladder_normal: Option<Vector3<f32>>,
+    respawn_point: SpawnPoint,
+    jump_timing: JumpTiming,
+    launch_timer: f32,
+}
+
+// Forgives a jump that's pressed shortly before landing, or shortly after walking off an edge
+struct JumpTiming {
+    buffer_window: f32,
+    coyote_window: f32,
+    buffer_timer: f32,
+    coyote_timer: f32,
 }
 
 impl Player {
-    pub fn new() -> Player {
+    pub fn new(spawn_point: SpawnPoint, settings: &MovementSettings) -> Player {
         Player {
             velocity: Vector3::zero(),
-            position: START_POSITION,
-            forward: Vector3::new(0.0, 0.0, -1.0),
-            prev_is_grounded: false,
+            position: spawn_point.position(),
+            view: ViewAngles::from_forward(spawn_point.facing()),
+            state: MovementState::Flying,
+            events: Vec::new(),
             gonna_jump: false,
-            enabled_fly_move: true,
+            ladder_normal: None,
+            respawn_point: spawn_point,
+            jump_timing: JumpTiming::new(settings),
+            launch_timer: 0.0,
         }
     }
 
-    pub fn tick(
-        &mut self,
-        keys: &Keys,
-        mouse: (f32, f32),
-        static_objects: &Vec<StaticObject>,
-        dt: f32,
-    ) {
-        mouse_look(&mut self.forward, mouse);
+    pub fn tick(&mut self, input: &PlayerInput, world: &World, dt: f32) {
+        let static_objects = &world.static_objects;
+
+        self.view.rotate(input.look);
 
-        if keys.get_key_down(Keycode::K) {
-            self.enabled_fly_move = !self.enabled_fly_move;
+        if input.toggle_fly {
+            if self.state == MovementState::Flying {
+                self.set_state(MovementState::Airborne);
+            } else {
+                self.set_state(MovementState::Flying);
+            }
         }
 
-        if self.enabled_fly_move {
-            fly_move(&mut self.position, &self.forward, &keys, dt);
+        if input.respawn {
+            self.respawn();
             return;
         }
 
-        if keys.get_key_down(Keycode::Space) {
+        if self.state == MovementState::Flying {
+            let forward = self.forward();
+            fly_move(&mut self.position, &forward, input, dt);
+            return;
+        }
+
+        if input.jump_pressed {
             self.gonna_jump = true;
-        } else if keys.get_key_up(Keycode::Space) {
+            self.jump_timing.press();
+        } else if input.jump_released {
             self.gonna_jump = false;
         }
 
+        // A press that was released before landing still counts within the buffer window
+        let is_buffered = self.jump_timing.is_buffered(dt);
+        let wants_jump = self.gonna_jump || is_buffered;
+
+        if let Some(water) = world.water_at(self.position) {
+            self.set_state(MovementState::Swimming);
+            self.swim(water.surface_height(), input, wants_jump, dt);
+            self.resolve_collisions(world);
+            return;
+        }
+
+        if let Some(ladder_normal) = self.ladder_normal {
+            let towards_ladder = Vector3::dot(horz(&self.forward()), -ladder_normal) > 0.0;
+            if input.move_axis.1 > 0.5 && towards_ladder {
+                self.set_state(MovementState::Climbing);
+                self.climb(ladder_normal, wants_jump, dt);
+                self.resolve_collisions(world);
+                return;
+            }
+        }
+
         let wish_dir = get_wish_dir(
-            &keys,
-            horz_norm(&self.forward).unwrap_or(Vector3::<f32>::zero()),
+            input,
+            horz_norm(&self.forward()).unwrap_or(Vector3::<f32>::zero()),
         );
 
         let (is_grounded, ground_normal) =
             grounded_check(&static_objects, self.position, horz_norm(&self.velocity));
 
+        // Otherwise the ground move would flatten the launch velocity on the next tick
+        let is_grounded = is_grounded && self.launch_timer <= 0.0;
+        self.launch_timer = (self.launch_timer - dt).max(0.0);
+
+        let was_on_ground = self.state.is_on_ground();
         if is_grounded {
             // Ground move
-            if self.prev_is_grounded && !self.gonna_jump {
+            self.jump_timing.touched_ground();
+
+            if !was_on_ground {
+                self.events.push(PlayerEvent::Landed {
+                    impact_speed: (-self.velocity.y).max(0.0),
+                });
+            }
+            if input.crouch {
+                self.set_state(MovementState::Crouched);
+            } else {
+                self.set_state(MovementState::Grounded);
+            }
+
+            if was_on_ground && !wants_jump {
                 apply_friction(&mut self.velocity, dt);
             }
 
-            accelerate(&mut self.velocity, wish_dir, GROUND_ACCELERATION, dt);
+            let max_speed = {
+                if self.state == MovementState::Crouched {
+                    MAX_SPEED_ON_ONE_DIMENSION * CROUCH_SPEED_SCALE
+                } else {
+                    MAX_SPEED_ON_ONE_DIMENSION
+                }
+            };
+            accelerate(
+                &mut self.velocity,
+                wish_dir,
+                GROUND_ACCELERATION,
+                max_speed,
+                dt,
+            );
 
             // No vetical velocity while grounded
             self.velocity = project_vector_on_plane(self.velocity, ground_normal);
 
-            if self.gonna_jump {
-                self.gonna_jump = false;
+            if wants_jump {
+                self.consume_jump();
 
                 // TODO: Add a fraction of horizontal velocity to the jump direction
                 self.velocity += Vector3::unit_y() * JUMP_FORCE;
+                self.events.push(PlayerEvent::Jumped);
+                self.set_state(MovementState::Airborne);
             }
+        } else if wants_jump && self.jump_timing.in_coyote_time() && self.velocity.y <= 0.0 {
+            // Walked off an edge a moment ago, the jump still counts as a ground jump
+            self.consume_jump();
+            self.velocity.y = JUMP_FORCE;
+            self.events.push(PlayerEvent::Jumped);
+            self.set_state(MovementState::Airborne);
         } else {
+            self.jump_timing.in_air(dt);
+            if was_on_ground {
+                self.events.push(PlayerEvent::LeftGround);
+            }
+            self.set_state(MovementState::Airborne);
+
             // Air move
             let air_coeff = {
                 if Vector3::dot(wish_dir, self.velocity) > 0.0 {
@@ -96,99 +199,338 @@ impl Player {
                 }
             };
 
-            accelerate(&mut self.velocity, wish_dir, air_coeff, dt);
+            accelerate(
+                &mut self.velocity,
+                wish_dir,
+                air_coeff,
+                MAX_SPEED_ON_ONE_DIMENSION,
+                dt,
+            );
             self.velocity -= Vector3::unit_y() * GRAVITY * dt;
         }
 
         self.position += self.velocity * dt;
 
-        let displacement = resolve_penetration(&static_objects, self.position);
-        self.position += displacement;
-        self.prev_is_grounded = is_grounded;
+        self.resolve_collisions(world);
+
+        if self.position.y < world.kill_height {
+            // Fell down, back to the last checkpoint
+            self.respawn();
+        }
+    }
+
+    pub fn set_respawn_point(&mut self, spawn_point: SpawnPoint) {
+        self.respawn_point = spawn_point;
+    }
+
+    // Puts the player in a known state, e.g. at the start of a demo
+    pub fn reset(&mut self, spawn_point: SpawnPoint, is_flying: bool) {
+        self.respawn_point = spawn_point;
+        if is_flying {
+            self.set_state(MovementState::Flying);
+        } else {
+            self.set_state(MovementState::Airborne);
+        }
+        self.respawn();
+    }
+
+    pub fn respawn(&mut self) {
+        self.position = self.respawn_point.position();
+        self.view = ViewAngles::from_forward(self.respawn_point.facing());
+        self.velocity = Vector3::zero();
+        if self.state != MovementState::Flying {
+            self.set_state(MovementState::Airborne);
+        }
+        self.events.push(PlayerEvent::Respawned);
+        self.gonna_jump = false;
+        self.jump_timing.reset();
+        self.launch_timer = 0.0;
+        self.ladder_normal = None;
+    }
+
+    pub fn launch(&mut self, launch: &Launch, world: &World) {
+        self.velocity = match launch {
+            Launch::Set(velocity) => Vector3::from(*velocity),
+            Launch::Add(velocity) => self.velocity + Vector3::from(*velocity),
+            Launch::ToTarget {
+                target,
+                apex_height,
+            } => match world.target_point(target) {
+                Some(target_position) => {
+                    launch_velocity_to(self.position, target_position, *apex_height)
+                }
+                None => {
+                    println!("Launch target {} couldn't be found", target);
+                    return;
+                }
+            },
+        };
+
+        self.launch_timer = LAUNCH_GROUND_IGNORE_TIME;
+        self.jump_timing.reset();
+        self.ladder_normal = None;
+        self.events.push(PlayerEvent::Launched);
+        if self.state != MovementState::Flying {
+            self.set_state(MovementState::Airborne);
+        }
+    }
+
+    pub fn teleport(&mut self, exit: &SpawnPoint, momentum: TeleportMomentum) {
+        self.position = exit.position();
+        self.ladder_normal = None;
+        self.events.push(PlayerEvent::Teleported);
+
+        let exit_view = ViewAngles::from_forward(exit.facing());
+        match momentum {
+            TeleportMomentum::Keep => {}
+            TeleportMomentum::Rotate => {
+                // Keeps the velocity pointing the same way relative to the view
+                let yaw_change = exit_view.yaw - self.view.yaw;
+                self.velocity =
+                    Quaternion::from_angle_y(Deg(yaw_change)).rotate_vector(self.velocity);
+                self.view = ViewAngles::new(exit_view.yaw, self.view.pitch);
+            }
+            TeleportMomentum::Zero => {
+                self.velocity = Vector3::zero();
+                self.view = exit_view;
+            }
+        }
+    }
+
+    pub fn boost(&mut self, multiplier: f32) {
+        self.velocity.x *= multiplier;
+        self.velocity.z *= multiplier;
+    }
+
+    fn swim(&mut self, surface_height: f32, input: &PlayerInput, wants_jump: bool, dt: f32) {
+        let at_surface = self.position.y > surface_height - WATER_SURFACE_OFFSET;
+
+        if at_surface && wants_jump {
+            // Jumping out at the edge of the water
+            self.consume_jump();
+            self.velocity.y = WATER_JUMP_FORCE;
+            self.position += self.velocity * dt;
+            return;
+        }
 
-        if self.position.y < -30.0 {
-            // Fell down, reset
-            self.velocity = Vector3::zero();
-            self.position = START_POSITION;
+        let mut wish_dir = get_swim_wish_dir(input, self.forward());
+        if at_surface && wish_dir.y > 0.0 {
+            // Float on the surface instead of swimming out of the water
+            wish_dir = horz(&wish_dir);
         }
+
+        self.velocity /= 1.0 + WATER_DRAG * dt;
+        accelerate(
+            &mut self.velocity,
+            wish_dir,
+            SWIM_ACCELERATION,
+            SWIM_MAX_SPEED,
+            dt,
+        );
+
+        if !at_surface {
+            self.velocity -= Vector3::unit_y() * (GRAVITY - WATER_BUOYANCY) * dt;
+        }
+
+        self.position += self.velocity * dt;
+    }
+
+    fn climb(&mut self, ladder_normal: Vector3<f32>, wants_jump: bool, dt: f32) {
+        if wants_jump {
+            // Kick off the ladder
+            self.consume_jump();
+            self.velocity =
+                ladder_normal * CLIMB_JUMP_OFF_SPEED + Vector3::unit_y() * JUMP_FORCE * 0.5;
+            self.position += self.velocity * dt;
+            return;
+        }
+
+        let vertical_dir = {
+            if self.forward().y > CLIMB_LOOK_DOWN_THRESHOLD {
+                1.0
+            } else {
+                -1.0
+            }
+        };
+
+        self.velocity = Vector3::unit_y() * vertical_dir * CLIMB_SPEED;
+        self.position += (self.velocity - ladder_normal * CLIMB_STICK_SPEED) * dt;
+    }
+
+    fn resolve_collisions(&mut self, world: &World) {
+        let (displacement, contacts) = resolve_penetration(&world.static_objects, self.position);
+        self.position += displacement;
+
+        self.ladder_normal = contacts
+            .iter()
+            .find(|c| world.static_objects[c.object_index].climbable)
+            .map(|c| horz(&c.normal));
     }
 
-    pub fn get_view_matrix(&self) -> Matrix4<f32> {
-        Matrix4::look_at(
-            self.position,
-            self.position + self.forward,
-            vec3(0.0, 1.0, 0.0),
-        )
+    pub fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.view.forward()
+    }
+
+    pub fn view(&self) -> ViewAngles {
+        self.view
+    }
+
+    fn consume_jump(&mut self) {
+        self.gonna_jump = false;
+        self.jump_timing.reset();
+    }
+
+    pub fn is_flying(&self) -> bool {
+        self.state == MovementState::Flying
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.state.is_on_ground()
+    }
+
+    pub fn state(&self) -> MovementState {
+        self.state
+    }
+
+    // Hands over the events since the last call
+    pub fn take_events(&mut self) -> Vec<PlayerEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn set_state(&mut self, state: MovementState) {
+        if state != self.state {
+            self.events.push(PlayerEvent::StateChanged {
+                from: self.state,
+                to: state,
+            });
+            self.state = state;
+        }
     }
 }
 
-fn mouse_look(forward: &mut Vector3<f32>, mouse: (f32, f32)) {
-    let (mouse_x, mouse_y) = mouse;
+impl JumpTiming {
+    fn new(settings: &MovementSettings) -> JumpTiming {
+        JumpTiming {
+            buffer_window: settings.jump_buffer_window,
+            coyote_window: settings.coyote_time_window,
+            buffer_timer: 0.0,
+            coyote_timer: 0.0,
+        }
+    }
 
-    let horz_rot = Quaternion::from_axis_angle(Vector3::unit_y(), Rad(-mouse_x) * SENSITIVITY);
-    *forward = horz_rot.rotate_vector(*forward);
+    fn press(&mut self) {
+        self.buffer_timer = self.buffer_window;
+    }
 
-    let left = forward.cross(Vector3::unit_y());
-    *forward =
-        Quaternion::from_axis_angle(left, Rad(-mouse_y) * SENSITIVITY).rotate_vector(*forward);
+    // Whether a press is still remembered on this tick, counting the window down
+    fn is_buffered(&mut self, dt: f32) -> bool {
+        let is_buffered = self.buffer_timer > 0.0;
+        self.buffer_timer = (self.buffer_timer - dt).max(0.0);
+        is_buffered
+    }
+
+    fn touched_ground(&mut self) {
+        self.coyote_timer = self.coyote_window;
+    }
+
+    fn in_air(&mut self, dt: f32) {
+        self.coyote_timer = (self.coyote_timer - dt).max(0.0);
+    }
+
+    fn in_coyote_time(&self) -> bool {
+        self.coyote_timer > 0.0
+    }
+
+    fn reset(&mut self) {
+        self.buffer_timer = 0.0;
+        self.coyote_timer = 0.0;
+    }
 }
 
-fn fly_move(position: &mut Point3<f32>, forward: &Vector3<f32>, keys: &Keys, dt: f32) {
+pub fn fly_move(position: &mut Point3<f32>, forward: &Vector3<f32>, input: &PlayerInput, dt: f32) {
     const FLY_SPEED: f32 = 0.01;
     let spd = {
-        if keys.get_key(Keycode::LShift) {
+        if input.fly_fast {
             FLY_SPEED * 2.0
         } else {
             FLY_SPEED
         }
     };
-    if keys.get_key(Keycode::W) {
-        *position += forward * spd * dt;
-    } else if keys.get_key(Keycode::S) {
-        *position -= forward * spd * dt;
-    }
 
-    if keys.get_key(Keycode::A) {
-        *position -= forward.cross(Vector3::unit_y()) * spd * dt;
-    } else if keys.get_key(Keycode::D) {
-        *position += forward.cross(Vector3::unit_y()) * spd * dt;
-    }
+    let (move_x, move_y) = input.move_axis;
+    *position += (forward * move_y + forward.cross(Vector3::unit_y()) * move_x) * spd * dt;
 
-    if keys.get_key(Keycode::Space) {
+    if input.jump {
         *position += Vector3::unit_y() * spd * dt;
-    } else if keys.get_key(Keycode::LCtrl) {
+    } else if input.crouch {
         *position -= Vector3::unit_y() * spd * dt;
     }
 }
 
-fn get_wish_dir(keys: &Keys, forward: Vector3<f32>) -> Vector3<f32> {
-    let mut vec = Vector3::<f32>::zero();
-    if keys.get_key(Keycode::W) {
-        vec += forward
-    } else if keys.get_key(Keycode::S) {
-        vec -= forward
-    }
-    if keys.get_key(Keycode::A) {
-        vec -= forward.cross(Vector3::unit_y())
-    } else if keys.get_key(Keycode::D) {
-        vec += forward.cross(Vector3::unit_y())
+fn get_wish_dir(input: &PlayerInput, forward: Vector3<f32>) -> Vector3<f32> {
+    let (move_x, move_y) = input.move_axis;
+    forward * move_y + forward.cross(Vector3::unit_y()) * move_x
+}
+
+fn get_swim_wish_dir(input: &PlayerInput, forward: Vector3<f32>) -> Vector3<f32> {
+    // Unlike walking, swimming follows the look direction, so looking up and pressing W goes up
+    let mut vec = get_wish_dir(input, forward);
+    if input.jump {
+        vec += Vector3::unit_y()
+    } else if input.crouch {
+        vec -= Vector3::unit_y()
     }
 
-    if vec.magnitude2() > 0.00001 {
+    if vec.magnitude2() > 1.0 {
         vec.normalize()
     } else {
-        vec // No input
+        vec
     }
 }
 
-fn accelerate(velocity: &mut Vector3<f32>, wish_dir: Vector3<f32>, accel_coeff: f32, dt: f32) {
+// Ballistic velocity that passes through `to`, ignoring air control and collisions
+pub fn launch_velocity_to(from: Point3<f32>, to: Point3<f32>, apex_height: f32) -> Vector3<f32> {
+    let apex_y = from.y.max(to.y) + apex_height.max(0.0);
+    let rise_time = (2.0 * (apex_y - from.y) / GRAVITY).sqrt();
+    let fall_time = (2.0 * (apex_y - to.y) / GRAVITY).sqrt();
+    let flight_time = rise_time + fall_time;
+
+    let horz_velocity = {
+        if flight_time > 0.0 {
+            horz(&(to - from)) / flight_time
+        } else {
+            Vector3::zero()
+        }
+    };
+    horz_velocity + Vector3::unit_y() * GRAVITY * rise_time
+}
+
+fn accelerate(
+    velocity: &mut Vector3<f32>,
+    wish_dir: Vector3<f32>,
+    accel_coeff: f32,
+    max_speed: f32,
+    dt: f32,
+) {
+    // A shorter wish_dir (partial stick tilt) lowers the target speed
+    let wish_scale = wish_dir.magnitude();
+    if wish_scale < 0.00001 {
+        return;
+    }
+    let wish_dir = wish_dir / wish_scale;
+    let max_speed = max_speed * wish_scale;
+
     let proj_speed = Vector3::dot(*velocity, wish_dir);
-    let add_speed = MAX_SPEED_ON_ONE_DIMENSION - proj_speed;
+    let add_speed = max_speed - proj_speed;
     if add_speed < 0.0 {
         return;
     }
 
-    let mut accel_amount = accel_coeff * MAX_SPEED_ON_ONE_DIMENSION * dt;
+    let mut accel_amount = accel_coeff * max_speed * dt;
     if accel_amount > add_speed {
         accel_amount = add_speed;
     }
@@ -211,3 +553,154 @@ fn apply_friction(velocity: &mut Vector3<f32>, dt: f32) {
     }
     *velocity *= drop_amount / speed;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::test_world;
+
+    #[test]
+    fn test_launch_velocity_lands_on_target() {
+        let from = Point3::new(0.0, 0.0, 0.0);
+        let to = Point3::new(10.0, 4.0, -5.0);
+        let velocity = launch_velocity_to(from, to, 2.0);
+
+        // Step the same integration as the air move, without air control
+        let (mut position, mut v) = (from, velocity);
+        let mut closest = f32::MAX;
+        for _ in 0..20000 {
+            v -= Vector3::unit_y() * GRAVITY;
+            position += v;
+            closest = closest.min((position - to).magnitude());
+        }
+        assert!(closest < 0.05, "missed the target by {}", closest);
+    }
+
+    const TICK: f32 = 16.0;
+
+    // Ends at x = 0
+    const LEDGE: ([[f32; 3]; 4], bool) = (
+        [
+            [-20.0, 0.0, -20.0],
+            [-20.0, 0.0, 20.0],
+            [0.0, 0.0, 20.0],
+            [0.0, 0.0, -20.0],
+        ],
+        false,
+    );
+
+    // The events of each tick, facing +x
+    fn run(
+        world: &World,
+        position: [f32; 3],
+        input: impl Fn(usize) -> PlayerInput,
+    ) -> Vec<Vec<PlayerEvent>> {
+        let spawn_point = SpawnPoint {
+            position,
+            facing: [1.0, 0.0, 0.0],
+        };
+        let mut player = Player::new(spawn_point, &MovementSettings::default());
+        player.reset(spawn_point, false);
+        player.take_events();
+        (0..300)
+            .map(|i| {
+                player.tick(&input(i), world, TICK);
+                player.take_events()
+            })
+            .collect()
+    }
+
+    // Pressed and released right away, so that only the buffer can remember it
+    fn tap_jump(input: PlayerInput, tick: usize, press_tick: usize) -> PlayerInput {
+        PlayerInput {
+            jump: tick == press_tick,
+            jump_pressed: tick == press_tick,
+            jump_released: tick == press_tick + 1,
+            ..input
+        }
+    }
+
+    fn first_tick_with(events: &[Vec<PlayerEvent>], event: impl Fn(&PlayerEvent) -> bool) -> usize {
+        events.iter().position(|e| e.iter().any(&event)).unwrap()
+    }
+
+    #[test]
+    fn test_tick_jumps_on_landing_with_buffered_press() {
+        let world = test_world(&[LEDGE]);
+        let falling = run(&world, [-10.0, 3.0, 0.0], |_| PlayerInput::default());
+        let landing = first_tick_with(&falling, |e| matches!(e, PlayerEvent::Landed { .. }));
+
+        let press_tick = landing - 3; // 48 ms early
+        let events = run(&world, [-10.0, 3.0, 0.0], |i| {
+            tap_jump(PlayerInput::default(), i, press_tick)
+        });
+        assert_eq!(
+            first_tick_with(&events, |e| *e == PlayerEvent::Jumped),
+            landing
+        );
+    }
+
+    #[test]
+    fn test_tick_jumps_after_walking_off_ledge() {
+        let world = test_world(&[LEDGE]);
+        let forward = PlayerInput {
+            move_axis: (0.0, 1.0),
+            ..PlayerInput::default()
+        };
+        let walking = run(&world, [-2.0, 1.5, 0.0], |_| forward);
+        let left_ground = first_tick_with(&walking, |e| *e == PlayerEvent::LeftGround);
+
+        let press_tick = left_ground + 2; // 32 ms late
+        let events = run(&world, [-2.0, 1.5, 0.0], |i| {
+            tap_jump(forward, i, press_tick)
+        });
+        assert_eq!(
+            first_tick_with(&events, |e| *e == PlayerEvent::Jumped),
+            press_tick
+        );
+
+        let press_tick = left_ground + 8; // 128 ms late
+        let events = run(&world, [-2.0, 1.5, 0.0], |i| {
+            tap_jump(forward, i, press_tick)
+        });
+        assert!(events.iter().flatten().all(|e| *e != PlayerEvent::Jumped));
+    }
+
+    #[test]
+    fn test_press_just_before_landing_jumps_on_touchdown() {
+        let mut timing = JumpTiming::new(&MovementSettings::default());
+        timing.press();
+        for _ in 0..6 {
+            assert!(timing.is_buffered(TICK)); // Still falling
+        }
+        assert!(timing.is_buffered(TICK)); // Touchdown, 96 ms after the press
+    }
+
+    #[test]
+    fn test_press_just_after_leaving_ledge_jumps() {
+        let mut timing = JumpTiming::new(&MovementSettings::default());
+        timing.touched_ground();
+        for _ in 0..5 {
+            timing.in_air(TICK);
+        }
+        timing.press();
+        assert!(timing.is_buffered(TICK));
+        assert!(timing.in_coyote_time());
+    }
+
+    #[test]
+    fn test_no_jump_once_windows_expired() {
+        let settings = MovementSettings::default();
+        let mut timing = JumpTiming::new(&settings);
+        timing.touched_ground();
+        timing.press();
+        let ticks =
+            (settings.jump_buffer_window.max(settings.coyote_time_window) / TICK).ceil() as usize;
+        for _ in 0..ticks {
+            timing.is_buffered(TICK);
+            timing.in_air(TICK);
+        }
+        assert!(!timing.is_buffered(TICK));
+        assert!(!timing.in_coyote_time());
+    }
+}
@@ -0,0 +1,243 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MovementState {
+    Grounded,
+    Crouched,
+    Airborne,
+    Swimming,
+    Climbing,
+    Flying, // Noclip, ignores collisions and gravity
+}
+
+impl MovementState {
+    pub fn is_on_ground(self) -> bool {
+        matches!(self, MovementState::Grounded | MovementState::Crouched)
+    }
+}
+
+// Things that happened to the player during a tick, for whoever wants to react to them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlayerEvent {
+    StateChanged {
+        from: MovementState,
+        to: MovementState,
+    },
+    Jumped,
+    Landed {
+        impact_speed: f32, // Downwards speed when touching the ground
+    },
+    LeftGround, // Walked or slid off an edge, jumps are reported separately
+    Launched,
+    Teleported,
+    Respawned,
+}
+
+// Writes the events as tab separated lines, prefixed with the simulation time in ms
+pub struct PlayerEventLog {
+    writer: Option<BufWriter<File>>,
+    time: f32,
+}
+
+impl PlayerEventLog {
+    pub fn new(path: Option<&Path>) -> PlayerEventLog {
+        let writer = path.and_then(|path| {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            match File::create(path) {
+                Ok(file) => Some(BufWriter::new(file)),
+                Err(e) => {
+                    println!("Couldn't create the player event log {:?}: {}", path, e);
+                    None
+                }
+            }
+        });
+
+        PlayerEventLog { writer, time: 0.0 }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn record(&mut self, events: &[PlayerEvent]) {
+        if let Some(writer) = self.writer.as_mut() {
+            for event in events {
+                let _ = writeln!(writer, "{:.0}\t{:?}", self.time, event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::Aabb;
+    use crate::input::PlayerInput;
+    use crate::player::Player;
+    use crate::settings::MovementSettings;
+    use crate::spawn_point::SpawnPoint;
+    use crate::water_volume::WaterVolume;
+    use crate::world::{test_world, World};
+    use cgmath::Point3;
+
+    const TICK: f32 = 16.0;
+    fn forward() -> PlayerInput {
+        PlayerInput {
+            move_axis: (0.0, 1.0),
+            ..PlayerInput::default()
+        }
+    }
+
+    // Ends at x = max_x
+    fn floor(max_x: f32) -> ([[f32; 3]; 4], bool) {
+        let corners = [
+            [-20.0, 0.0, -20.0],
+            [-20.0, 0.0, 20.0],
+            [max_x, 0.0, 20.0],
+            [max_x, 0.0, -20.0],
+        ];
+        (corners, false)
+    }
+
+    // Facing +x, falling
+    fn spawn(position: [f32; 3]) -> Player {
+        let spawn_point = SpawnPoint {
+            position,
+            facing: [1.0, 0.0, 0.0],
+        };
+        let mut player = Player::new(spawn_point, &MovementSettings::default());
+        player.reset(spawn_point, false);
+        player.take_events();
+        player
+    }
+
+    // The events of every tick until the condition holds
+    fn tick_until(
+        player: &mut Player,
+        world: &World,
+        input: PlayerInput,
+        condition: impl Fn(&Player) -> bool,
+    ) -> Vec<PlayerEvent> {
+        let mut events = Vec::new();
+        for _ in 0..500 {
+            player.tick(&input, world, TICK);
+            events.extend(player.take_events());
+            if condition(player) {
+                return events;
+            }
+        }
+        panic!("still {:?} at {:?}", player.state(), player.position());
+    }
+
+    fn changed(from: MovementState, to: MovementState) -> PlayerEvent {
+        PlayerEvent::StateChanged { from, to }
+    }
+
+    #[test]
+    fn test_lands_then_jumps() {
+        let world = test_world(&[floor(20.0)]);
+        let mut player = spawn([0.0, 3.0, 0.0]);
+
+        let events = tick_until(&mut player, &world, PlayerInput::default(), |p| {
+            p.is_grounded()
+        });
+        assert_eq!(player.state(), MovementState::Grounded);
+        assert!(events.contains(&changed(MovementState::Airborne, MovementState::Grounded)));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, PlayerEvent::Landed { impact_speed } if *impact_speed > 0.0)));
+
+        let jump = PlayerInput {
+            jump: true,
+            jump_pressed: true,
+            ..PlayerInput::default()
+        };
+        player.tick(&jump, &world, TICK);
+        assert_eq!(
+            player.take_events(),
+            vec![
+                PlayerEvent::Jumped,
+                changed(MovementState::Grounded, MovementState::Airborne)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walking_off_a_ledge_leaves_ground() {
+        let world = test_world(&[floor(0.0)]);
+        let mut player = spawn([-2.0, 1.5, 0.0]);
+        tick_until(&mut player, &world, PlayerInput::default(), |p| {
+            p.is_grounded()
+        });
+
+        let events = tick_until(&mut player, &world, forward(), |p| !p.is_grounded());
+        assert_eq!(player.state(), MovementState::Airborne);
+        assert!(events.contains(&PlayerEvent::LeftGround));
+        assert!(events.contains(&changed(MovementState::Grounded, MovementState::Airborne)));
+        assert!(!events.contains(&PlayerEvent::Jumped));
+    }
+
+    #[test]
+    fn test_enters_and_leaves_water() {
+        let mut world = test_world(&[]);
+        let bounds = Aabb::new(
+            Point3::new(-10.0, -10.0, -10.0),
+            Point3::new(10.0, 10.0, 10.0),
+        );
+        world.water_volumes.push(WaterVolume::new(bounds));
+        let mut player = spawn([0.0, 0.0, 0.0]);
+
+        player.tick(&PlayerInput::default(), &world, TICK);
+        assert_eq!(player.state(), MovementState::Swimming);
+        assert_eq!(
+            player.take_events(),
+            vec![changed(MovementState::Airborne, MovementState::Swimming)]
+        );
+
+        world.water_volumes.clear();
+        player.tick(&PlayerInput::default(), &world, TICK);
+        assert_eq!(player.state(), MovementState::Airborne);
+        assert_eq!(
+            player.take_events(),
+            vec![changed(MovementState::Swimming, MovementState::Airborne)]
+        );
+    }
+
+    #[test]
+    fn test_climbs_while_pushing_into_a_ladder() {
+        // Faces -x at x = 1
+        let ladder = (
+            [
+                [1.0, 0.0, -5.0],
+                [1.0, 0.0, 5.0],
+                [1.0, 10.0, 5.0],
+                [1.0, 10.0, -5.0],
+            ],
+            true,
+        );
+        let world = test_world(&[floor(20.0), ladder]);
+        let mut player = spawn([0.0, 1.5, 0.0]);
+        tick_until(&mut player, &world, PlayerInput::default(), |p| {
+            p.is_grounded()
+        });
+
+        let events = tick_until(&mut player, &world, forward(), |p| {
+            p.state() == MovementState::Climbing
+        });
+        assert!(events.contains(&changed(MovementState::Grounded, MovementState::Climbing)));
+
+        player.tick(&PlayerInput::default(), &world, TICK);
+        assert_ne!(player.state(), MovementState::Climbing);
+        assert!(player.take_events().iter().any(|e| matches!(
+            e,
+            PlayerEvent::StateChanged {
+                from: MovementState::Climbing,
+                ..
+            }
+        )));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::render::material::create_vertex_buffers;
+use crate::render::shader::Shader;
+use crate::render::texture::create_white_texture;
+use crate::render::{BufferHandle, TextureHandle};
+use cgmath::*;
+use std::f32::consts::PI;
+
+const CAPSULE_RADIUS: f32 = 0.5;
+const CAPSULE_HALF_HEIGHT: f32 = 0.5;
+const SEGMENTS: u32 = 16;
+const RINGS: u32 = 8; // Has to be even, half of them belong to each hemisphere
+const EYE_SCALE: f32 = 0.25;
+
+// Player-sized capsule, for things that move like a player but aren't the camera
+pub struct CapsuleModel {
+    vao: BufferHandle,
+    vbo: BufferHandle,
+    ibo: BufferHandle,
+    texture: TextureHandle,
+    index_data: Vec<u32>,
+}
+
+impl CapsuleModel {
+    pub fn new() -> CapsuleModel {
+        let (vertex_data, index_data) = create_capsule_vertex_array();
+        let (vao, vbo, ibo) = unsafe { create_vertex_buffers(&vertex_data, &index_data) };
+        let texture = unsafe { create_white_texture() };
+
+        CapsuleModel {
+            vao,
+            vbo,
+            ibo,
+            texture,
+            index_data,
+        }
+    }
+
+    pub unsafe fn draw(
+        &self,
+        shader: &Shader,
+        position: Point3<f32>,
+        forward: Vector3<f32>,
+        color: (f32, f32, f32, f32),
+    ) {
+        // The body, and a small blob in front of it to show where it's looking
+        let eye_position = position + forward * CAPSULE_RADIUS + Vector3::unit_y() * 0.3;
+        let transforms = [
+            Matrix4::from_translation(position.to_vec()),
+            Matrix4::from_translation(eye_position.to_vec()) * Matrix4::from_scale(EYE_SCALE),
+        ];
+        self.draw_transformed(shader, &transforms, color);
+    }
+
+    // One capsule per transform, the untransformed capsule is 1 wide and 2 tall
+    pub unsafe fn draw_transformed(
+        &self,
+        shader: &Shader,
+        transforms: &[Matrix4<f32>],
+        color: (f32, f32, f32, f32),
+    ) {
+        shader.set_vec4("u_tint", color.0, color.1, color.2, color.3);
+        gl::DepthMask(gl::FALSE);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture);
+        gl::BindVertexArray(self.vao);
+
+        for transform in transforms.iter() {
+            shader.set_mat4("u_model", *transform);
+            gl::DrawElements(
+                gl::TRIANGLES,
+                self.index_data.len() as i32,
+                gl::UNSIGNED_INT,
+                self.index_data.as_ptr() as *const std::os::raw::c_void,
+            );
+        }
+
+        gl::BindVertexArray(0);
+        gl::DepthMask(gl::TRUE);
+        shader.set_vec4("u_tint", 1.0, 1.0, 1.0, 1.0);
+    }
+}
+
+impl Drop for CapsuleModel {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ibo);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+// Two hemispheres with the equator ring duplicated, so that the gap between them becomes the cylinder
+fn create_capsule_vertex_array() -> (Vec<f32>, Vec<u32>) {
+    let rings = (0..=RINGS / 2)
+        .map(|i| (i, CAPSULE_HALF_HEIGHT))
+        .chain((RINGS / 2..=RINGS).map(|i| (i, -CAPSULE_HALF_HEIGHT)))
+        .collect::<Vec<_>>();
+
+    let mut vertex_data = Vec::new();
+    for (ring_index, (i, offset)) in rings.iter().enumerate() {
+        let phi = PI * (*i as f32) / RINGS as f32;
+        for j in 0..=SEGMENTS {
+            let theta = 2.0 * PI * (j as f32) / SEGMENTS as f32;
+            let normal = Vector3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+            let position = normal * CAPSULE_RADIUS + Vector3::unit_y() * *offset;
+
+            vertex_data.extend_from_slice(&[
+                position.x,
+                position.y,
+                position.z,
+                j as f32 / SEGMENTS as f32,
+                ring_index as f32 / (rings.len() - 1) as f32,
+                normal.x,
+                normal.y,
+                normal.z,
+            ]);
+        }
+    }
+
+    let ring_size = SEGMENTS + 1;
+    let mut index_data = Vec::new();
+    for r in 0..(rings.len() as u32 - 1) {
+        for j in 0..SEGMENTS {
+            let a = r * ring_size + j;
+            let b = (r + 1) * ring_size + j;
+            let c = b + 1;
+            let d = a + 1;
+            index_data.extend_from_slice(&[a, c, b, a, d, c]);
+        }
+    }
+
+    (vertex_data, index_data)
+}
@@ -1,7 +1,9 @@
+use crate::asset_cache::AssetCache;
 use crate::render::shader::*;
 use crate::render::*;
 use crate::static_object::StaticObject;
 use cgmath::*;
+use std::rc::Rc;
 
 pub struct DirectionalLight {
     pub direction: Vector3<f32>,
@@ -10,12 +12,12 @@ pub struct DirectionalLight {
     pub color: Vector4<f32>,
 
     fbo: BufferHandle,
-    shader: Shader,
+    shader: Rc<Shader>,
     pub depth_texture_handle: TextureHandle,
 }
 
 impl DirectionalLight {
-    pub fn new() -> DirectionalLight {
+    pub fn new(cache: &mut AssetCache) -> DirectionalLight {
         let s = 100.0;
         let direction = Vector3::new(100.0, -100.0, -20.0);
         let view = Matrix4::look_at(
@@ -29,7 +31,8 @@ impl DirectionalLight {
         let mut depth_fbo: BufferHandle = 0;
         let depth_texture_handle: TextureHandle;
 
-        let shader = Shader::from_file("src/shaders/shadowmap_depth_directional.glsl", false)
+        let shader = cache
+            .shader("src/shaders/shadowmap_depth_directional.glsl", false)
             .expect("\nProblem loading directional shadowmap depth shader\n");
 
         unsafe {
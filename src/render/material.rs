@@ -1,116 +1,135 @@
 extern crate tobj;
-use crate::render::texture;
+use crate::asset_cache::AssetCache;
+use crate::asset_error::LoadReport;
+use crate::mesh::{diffuse_texture_path, diffuse_tint};
+use crate::render::texture::Texture;
 use crate::render::*;
 use gl::types::*;
+use std::rc::Rc;
 
-#[derive(Copy, Clone, Debug)]
-struct Color {
-    r: f32,
-    g: f32,
-    b: f32,
-}
-
-enum MaterialType {
-    Texture(GLuint),
-    Color(Color),
-}
+const CLIMBABLE_MATERIAL_TAG: &str = "ladder";
+const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
 pub struct Material {
+    pub name: String,
+    pub texture_path: Option<String>,
     vbo: BufferHandle,
     ibo: BufferHandle,
     vao: BufferHandle,
-    m_type: MaterialType,
+    texture: Rc<Texture>, // Shared with every other material that uses the same file
     index_data: Vec<u32>,
+    vertex_bytes: usize,
+    tint: [f32; 4], // Multiplies the texture, e.g. a glTF base color factor
 }
 
 impl Material {
-    pub fn new(vertex_data: Vec<f32>, index_data: Vec<u32>, tobj_mat: tobj::Material) -> Material {
-        let (mat_type, _shader_path) = get_material_type(&tobj_mat);
+    // A texture that fails to load is reported and replaced with plain white, the rest still loads.
+    // Untextured materials are plain white tinted with their diffuse color.
+    pub fn new(
+        vertex_data: Vec<f32>,
+        index_data: Vec<u32>,
+        tobj_mat: tobj::Material,
+        cache: &mut AssetCache,
+        report: &mut LoadReport,
+    ) -> Material {
+        let texture_path = diffuse_texture_path(&tobj_mat);
+        let (texture, tint) = match &texture_path {
+            Some(texture_path) => {
+                let texture = cache.texture(texture_path).unwrap_or_else(|e| {
+                    report.push(e);
+                    cache.white_texture()
+                });
+                (texture, WHITE)
+            }
+            None => (cache.white_texture(), diffuse_tint(&tobj_mat)),
+        };
+
+        Material::with_texture(
+            tobj_mat.name,
+            vertex_data,
+            index_data,
+            texture,
+            texture_path,
+            tint,
+        )
+    }
 
-        let mut vbo: GLuint = 0;
-        let mut ibo: GLuint = 0;
-        let mut vao: GLuint = 0;
+    // For materials whose texture is already loaded, texture_path is only kept for hot reloading
+    pub fn with_texture(
+        name: String,
+        vertex_data: Vec<f32>,
+        index_data: Vec<u32>,
+        texture: Rc<Texture>,
+        texture_path: Option<String>,
+        tint: [f32; 4],
+    ) -> Material {
+        Material::from_vertex_bytes(
+            name,
+            as_bytes(&vertex_data),
+            index_data,
+            texture,
+            texture_path,
+            tint,
+        )
+    }
 
-        unsafe {
-            gl::GenBuffers(1, &mut vbo);
-            gl::GenBuffers(1, &mut ibo);
-            gl::GenVertexArrays(1, &mut vao);
-
-            const SIZEOF_FLOAT: usize = std::mem::size_of::<f32>();
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertex_data.len() * SIZEOF_FLOAT) as GLsizeiptr,
-                vertex_data.as_ptr() as *const GLvoid,
-                gl::STATIC_DRAW,
-            );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ibo);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (index_data.len() * SIZEOF_FLOAT) as GLsizeiptr,
-                index_data.as_ptr() as *const GLvoid,
-                gl::STATIC_DRAW,
-            );
-            gl::BindVertexArray(vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-
-            // Position
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (8 * SIZEOF_FLOAT) as i32,
-                std::ptr::null(),
-            );
-
-            // Texcoord
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                (8 * SIZEOF_FLOAT) as i32,
-                (3 * SIZEOF_FLOAT) as *const GLvoid,
-            );
-
-            // Normals
-            gl::EnableVertexAttribArray(2);
-            gl::VertexAttribPointer(
-                2,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (8 * SIZEOF_FLOAT) as i32,
-                (5 * SIZEOF_FLOAT) as *const GLvoid,
-            );
-
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindVertexArray(0);
-
-            check_gl_error("material");
+    // Vertex data that's already interleaved and in bytes, e.g. straight out of a cooked file
+    pub fn from_vertex_bytes(
+        name: String,
+        vertex_bytes: &[u8],
+        index_data: Vec<u32>,
+        texture: Rc<Texture>,
+        texture_path: Option<String>,
+        tint: [f32; 4],
+    ) -> Material {
+        let (vao, vbo, ibo) = unsafe { create_buffers_from_bytes(vertex_bytes, &index_data) };
+
+        Material {
+            name,
+            texture_path,
+            vbo,
+            ibo,
+            vao,
+            texture,
+            index_data,
+            vertex_bytes: vertex_bytes.len(),
+            tint,
         }
+    }
 
+    // Never uploaded, for tests without a GL context. It has to be leaked, dropping it calls into GL.
+    #[cfg(test)]
+    pub fn placeholder(name: &str) -> Material {
         Material {
-            vbo: vbo,
-            ibo: ibo,
-            vao: vao,
-            m_type: mat_type,
-            index_data: index_data,
+            name: name.to_string(),
+            texture_path: None,
+            vbo: 0,
+            ibo: 0,
+            vao: 0,
+            texture: Rc::new(Texture::placeholder()),
+            index_data: Vec::new(),
+            vertex_bytes: 0,
+            tint: WHITE,
         }
     }
 
+    // Vertex and index buffers, the texture is counted by the cache
+    pub fn buffer_bytes(&self) -> usize {
+        self.vertex_bytes + self.index_data.len() * std::mem::size_of::<u32>()
+    }
+
+    pub fn tint(&self) -> [f32; 4] {
+        self.tint
+    }
+
+    // Materials with "ladder" in their names can be climbed, regardless of the scene
+    pub fn is_climbable(&self) -> bool {
+        self.name.to_lowercase().contains(CLIMBABLE_MATERIAL_TAG)
+    }
+
     pub unsafe fn draw(&self) {
-        match self.m_type {
-            MaterialType::Texture(texture_handle) => {
-                gl::ActiveTexture(gl::TEXTURE0);
-                gl::BindTexture(gl::TEXTURE_2D, texture_handle);
-            }
-            _ => unreachable!(),
-        }
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, self.texture.handle());
 
         gl::BindVertexArray(self.vao);
         gl::DrawElements(
@@ -128,27 +147,96 @@ impl Drop for Material {
             gl::DeleteVertexArrays(1, &self.vao);
             gl::DeleteBuffers(1, &self.vbo);
             gl::DeleteBuffers(1, &self.ibo);
-            if let MaterialType::Texture(texture) = self.m_type {
-                gl::DeleteTextures(1, &texture);
-            }
         }
     }
 }
 
-fn get_material_type(tobj_mat: &tobj::Material) -> (MaterialType, &str) {
-    if !tobj_mat.diffuse_texture.is_empty() {
-        let texture =
-            texture::load_from_file(format!("assets/{}", tobj_mat.diffuse_texture).as_str());
-
-        (MaterialType::Texture(texture), "src/shaders/triangle.glsl")
-    } else {
-        (
-            MaterialType::Color(Color {
-                r: tobj_mat.diffuse[0],
-                g: tobj_mat.diffuse[1],
-                b: tobj_mat.diffuse[2],
-            }),
-            "src/shaders/color.glsl",
+// Interleaved position (3), texcoord (2) and normal (3) layout, returns (vao, vbo, ibo)
+pub unsafe fn create_vertex_buffers(
+    vertex_data: &[f32],
+    index_data: &[u32],
+) -> (BufferHandle, BufferHandle, BufferHandle) {
+    create_buffers_from_bytes(as_bytes(vertex_data), index_data)
+}
+
+fn as_bytes(vertex_data: &[f32]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(
+            vertex_data.as_ptr() as *const u8,
+            vertex_data.len() * SIZEOF_FLOAT,
         )
     }
 }
+
+// The same layout, the bytes don't need to be aligned since they're only copied to the GPU
+unsafe fn create_buffers_from_bytes(
+    vertex_bytes: &[u8],
+    index_data: &[u32],
+) -> (BufferHandle, BufferHandle, BufferHandle) {
+    let mut vbo: GLuint = 0;
+    let mut ibo: GLuint = 0;
+    let mut vao: GLuint = 0;
+
+    gl::GenBuffers(1, &mut vbo);
+    gl::GenBuffers(1, &mut ibo);
+    gl::GenVertexArrays(1, &mut vao);
+
+    const SIZEOF_FLOAT: usize = std::mem::size_of::<f32>();
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    gl::BufferData(
+        gl::ARRAY_BUFFER,
+        vertex_bytes.len() as GLsizeiptr,
+        vertex_bytes.as_ptr() as *const GLvoid,
+        gl::STATIC_DRAW,
+    );
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ibo);
+    gl::BufferData(
+        gl::ELEMENT_ARRAY_BUFFER,
+        (index_data.len() * SIZEOF_FLOAT) as GLsizeiptr,
+        index_data.as_ptr() as *const GLvoid,
+        gl::STATIC_DRAW,
+    );
+    gl::BindVertexArray(vao);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+
+    // Position
+    gl::EnableVertexAttribArray(0);
+    gl::VertexAttribPointer(
+        0,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        (8 * SIZEOF_FLOAT) as i32,
+        std::ptr::null(),
+    );
+
+    // Texcoord
+    gl::EnableVertexAttribArray(1);
+    gl::VertexAttribPointer(
+        1,
+        2,
+        gl::FLOAT,
+        gl::FALSE,
+        (8 * SIZEOF_FLOAT) as i32,
+        (3 * SIZEOF_FLOAT) as *const GLvoid,
+    );
+
+    // Normals
+    gl::EnableVertexAttribArray(2);
+    gl::VertexAttribPointer(
+        2,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        (8 * SIZEOF_FLOAT) as i32,
+        (5 * SIZEOF_FLOAT) as *const GLvoid,
+    );
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    gl::BindVertexArray(0);
+
+    check_gl_error("material");
+
+    (vao, vbo, ibo)
+}
@@ -1,10 +1,11 @@
+mod capsule;
 mod directional_light;
 pub mod material;
 mod point_light;
 pub mod renderer;
-mod shader;
+pub mod shader;
 mod skybox;
-mod texture;
+pub mod texture;
 pub mod ui;
 mod ui_batch;
 
@@ -3,13 +3,16 @@ use crate::render::shader::*;
 use crate::render::{BufferHandle, TextureHandle};
 use crate::static_object::StaticObject;
 use cgmath::*;
+use std::rc::Rc;
 
 pub struct PointLight {
     pub position: Point3<f32>,
     pub intensity: f32,
     pub attenuation: f32,
 
-    shader: Shader,
+    light_index: u32,
+    shadow_matrices: [Matrix4<f32>; 6],
+    shader: Rc<Shader>, // The same for every light, so the light's uniforms are set before each use
 }
 
 impl PointLight {
@@ -18,10 +21,8 @@ impl PointLight {
         intensity: f32,
         attenuation: f32,
         light_index: u32,
+        shader: Rc<Shader>,
     ) -> PointLight {
-        let shader = Shader::from_file("src/shaders/shadowmap_depth_point.glsl", true)
-            .expect("\nProblem loading point shadowmap depth shader\n");
-
         let proj = cgmath::perspective(
             cgmath::Deg(90.0),
             render::SHADOWMAP_SIZE as f32 / render::SHADOWMAP_SIZE as f32,
@@ -62,29 +63,35 @@ impl PointLight {
             Vector3::new(0.0, -1.0, 0.0),
         );
 
-        unsafe {
-            shader.set_used();
-            shader.set_mat4("u_shadow_matrices[0]", proj * v0);
-            shader.set_mat4("u_shadow_matrices[1]", proj * v1);
-            shader.set_mat4("u_shadow_matrices[2]", proj * v2);
-            shader.set_mat4("u_shadow_matrices[3]", proj * v3);
-            shader.set_mat4("u_shadow_matrices[4]", proj * v4);
-            shader.set_mat4("u_shadow_matrices[5]", proj * v5);
-            shader.set_f32("u_far_plane", render::FAR_PLANE);
-            shader.set_vec3("u_light_pos", pos.x, pos.y, pos.z);
-            shader.set_i32("u_light_index", light_index as i32);
-        }
-
         PointLight {
             position: position,
             intensity: intensity,
             attenuation: attenuation,
+            light_index,
+            shadow_matrices: [
+                proj * v0,
+                proj * v1,
+                proj * v2,
+                proj * v3,
+                proj * v4,
+                proj * v5,
+            ],
             shader: shader,
         }
     }
 
     pub unsafe fn fill_depth_cubemap(&mut self, static_objects: &Vec<StaticObject>) {
         self.shader.set_used();
+        for (i, shadow_matrix) in self.shadow_matrices.iter().enumerate() {
+            self.shader
+                .set_mat4(format!("u_shadow_matrices[{}]", i).as_str(), *shadow_matrix);
+        }
+        self.shader.set_f32("u_far_plane", render::FAR_PLANE);
+        let pos = self.position;
+        self.shader.set_vec3("u_light_pos", pos.x, pos.y, pos.z);
+        self.shader
+            .set_i32("u_light_index", self.light_index as i32);
+
         for obj in static_objects {
             self.shader.set_mat4("u_model", obj.transform);
             obj.material.draw();
@@ -1,3 +1,6 @@
+use crate::asset_cache::AssetCache;
+use crate::light_source::{PointLightSource, MAX_POINT_LIGHTS};
+use crate::render::capsule::CapsuleModel;
 use crate::render::directional_light::*;
 use crate::render::point_light::*;
 use crate::render::shader::*;
@@ -6,6 +9,18 @@ use crate::render::*;
 use crate::static_object::StaticObject;
 use crate::*;
 use cgmath::*;
+use std::rc::Rc;
+
+const WATER_FOG_COLOR: (f32, f32, f32) = (0.05, 0.15, 0.2);
+const WATER_FOG_DENSITY: f32 = 0.08;
+const GHOST_COLOR: (f32, f32, f32, f32) = (0.4, 0.8, 1.0, 0.35);
+const PLAYER_COLOR: (f32, f32, f32, f32) = (1.0, 0.5, 0.2, 0.9);
+const SELECTED_TINT: [f32; 4] = [1.0, 0.8, 0.3, 1.0];
+const GIZMO_LENGTH: f32 = 1.5;
+const GIZMO_THICKNESS: f32 = 0.08;
+const GIZMO_INACTIVE_ALPHA: f32 = 0.35;
+const DEFAULT_FOV: f32 = 45.0;
+const CLEAR_COLOR: (f32, f32, f32) = (0.1, 0.05, 0.05);
 
 #[allow(dead_code)] // The glContext needs to be kept alive, even though not being read
 pub struct Renderer {
@@ -16,12 +31,15 @@ pub struct Renderer {
     point_lights: Vec<PointLight>,
     point_light_cubemap_handle: TextureHandle,
     point_light_fbo_handle: BufferHandle,
-    world_shader: Shader,
+    point_light_shader: Rc<Shader>,
+    world_shader: Rc<Shader>,
     draw_fbo: BufferHandle,
+    capsule_model: CapsuleModel,
+    selection: Option<editor::Selection>, // Highlighted, with a gizmo on top
 }
 
 impl Renderer {
-    pub fn init(sdl_context: &sdl2::Sdl) -> Self {
+    pub fn init(sdl_context: &sdl2::Sdl, cache: &mut AssetCache) -> Self {
         let sdl_video = sdl_context.video().unwrap();
         let gl_attr = sdl_video.gl_attr();
         gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
@@ -37,32 +55,24 @@ impl Renderer {
         let gl_context = window.gl_create_context().unwrap();
         gl::load_with(|s| sdl_video.gl_get_proc_address(s) as *const std::os::raw::c_void);
 
-        let projection = cgmath::perspective(
-            cgmath::Deg(45.0),
-            SCREEN_SIZE.0 as f32 / SCREEN_SIZE.1 as f32,
-            NEAR_PLANE,
-            FAR_PLANE,
-        );
-        let directional_light = DirectionalLight::new();
-
-        let point_light1 = PointLight::new(Point3::new(24.0, 2.0, -3.0), 2.0, 0.25, 0);
-        let point_light2 =
-            PointLight::new(Point3::new(-82.10625, 2.5329967, 57.53004), 1.5, 0.05, 1);
-        let point_light3 =
-            PointLight::new(Point3::new(-35.36219, 1.324518, 73.998116), 1.5, 0.2, 2);
-
-        let point_light4 = PointLight::new(Point3::new(2.986008, 1.8637276, 50.22367), 1.0, 0.2, 3);
+        let projection = create_projection(DEFAULT_FOV);
+        let directional_light = DirectionalLight::new(cache);
 
-        let point_lights = vec![point_light1, point_light2, point_light3, point_light4];
+        // The lights come from the scene, see set_point_lights
+        let point_lights: Vec<PointLight> = Vec::new();
 
         let point_light_cubemap_handle =
-            unsafe { render::point_light::create_cubemap_array(point_lights.len()) };
+            unsafe { render::point_light::create_cubemap_array(point_lights.len().max(1)) };
         let point_light_fbo_handle = unsafe {
             render::point_light::create_point_light_framebuffer(point_light_cubemap_handle)
         };
 
-        let world_shader = Shader::from_file("src/shaders/triangle.glsl", false)
+        let world_shader = cache
+            .shader("src/shaders/triangle.glsl", false)
             .expect("\nProblem loading world shader\n");
+        let point_light_shader = cache
+            .shader("src/shaders/shadowmap_depth_point.glsl", true)
+            .expect("\nProblem loading point shadowmap depth shader\n");
 
         let draw_fbo = unsafe { create_draw_backbuffer() };
 
@@ -72,32 +82,23 @@ impl Renderer {
             gl::Enable(gl::CULL_FACE);
             gl::CullFace(gl::BACK);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            gl::ClearColor(0.1, 0.05, 0.05, 1.0);
+            gl::ClearColor(CLEAR_COLOR.0, CLEAR_COLOR.1, CLEAR_COLOR.2, 1.0);
 
             world_shader.set_used();
             world_shader.set_i32("u_texture0", 0);
             world_shader.set_i32("u_shadowmap_directional", 1);
             world_shader.set_i32("u_shadowmaps_point", 2);
-            world_shader.set_i32("u_point_light_count", point_lights.len() as i32);
-
-            for (i, point_light) in point_lights.iter().enumerate() {
-                world_shader.set_vec3(
-                    format!("u_point_lights[{}].position", i).as_str(),
-                    point_light.position.x,
-                    point_light.position.y,
-                    point_light.position.z,
-                );
-                world_shader.set_f32(
-                    format!("u_point_lights[{}].intensity", i).as_str(),
-                    point_light.intensity,
-                );
-                world_shader.set_f32(
-                    format!("u_point_lights[{}].attenuation", i).as_str(),
-                    point_light.attenuation,
-                );
-            }
+            set_point_light_uniforms(&world_shader, &point_lights);
 
             world_shader.set_f32("u_far_plane", FAR_PLANE);
+            world_shader.set_vec3(
+                "u_water_fog_color",
+                WATER_FOG_COLOR.0,
+                WATER_FOG_COLOR.1,
+                WATER_FOG_COLOR.2,
+            );
+            world_shader.set_f32("u_water_fog_density", WATER_FOG_DENSITY);
+            world_shader.set_vec4("u_tint", 1.0, 1.0, 1.0, 1.0);
 
             world_shader.set_vec3(
                 "u_directional_light_dir",
@@ -138,13 +139,61 @@ impl Renderer {
             point_lights: point_lights,
             point_light_cubemap_handle: point_light_cubemap_handle,
             point_light_fbo_handle: point_light_fbo_handle,
+            point_light_shader,
 
-            skybox: Skybox::new(projection),
+            skybox: Skybox::new(projection, cache),
             draw_fbo: draw_fbo,
+            capsule_model: CapsuleModel::new(),
+            selection: None,
         }
     }
 
-    pub unsafe fn render(&mut self, static_objects: &Vec<StaticObject>, player_v: Matrix4<f32>) {
+    // Replaces all the point lights, along with their shadow cubemaps
+    pub unsafe fn set_point_lights(&mut self, light_sources: &[PointLightSource]) {
+        gl::DeleteFramebuffers(1, &self.point_light_fbo_handle);
+        gl::DeleteTextures(1, &self.point_light_cubemap_handle);
+
+        // Scenes with more are reported when they're loaded
+        self.point_lights = light_sources
+            .iter()
+            .take(MAX_POINT_LIGHTS)
+            .enumerate()
+            .map(|(i, source)| {
+                let position = Point3::from(source.position);
+                PointLight::new(
+                    position,
+                    source.intensity,
+                    source.attenuation,
+                    i as u32,
+                    Rc::clone(&self.point_light_shader),
+                )
+            })
+            .collect();
+
+        // A cubemap array can't be empty, the shader doesn't sample it without lights anyway
+        self.point_light_cubemap_handle =
+            render::point_light::create_cubemap_array(self.point_lights.len().max(1));
+        self.point_light_fbo_handle =
+            render::point_light::create_point_light_framebuffer(self.point_light_cubemap_handle);
+        set_point_light_uniforms(&self.world_shader, &self.point_lights);
+
+        gl::ActiveTexture(gl::TEXTURE2);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, self.point_light_cubemap_handle);
+    }
+
+    pub fn set_selection(&mut self, selection: Option<editor::Selection>) {
+        self.selection = selection;
+    }
+
+    pub unsafe fn render(
+        &mut self,
+        static_objects: &Vec<StaticObject>,
+        player_v: Matrix4<f32>,
+        fov: f32,
+        underwater: bool,
+        ghost: Option<(Point3<f32>, Vector3<f32>)>,
+        player: Option<(Point3<f32>, Vector3<f32>)>,
+    ) {
         gl::Disable(gl::CULL_FACE);
         self.directional_light.fill_depth_texture(&static_objects);
 
@@ -161,26 +210,72 @@ impl Renderer {
         gl::Enable(gl::CULL_FACE);
         gl::BindFramebuffer(gl::FRAMEBUFFER, self.draw_fbo);
         self.world_shader.set_used();
+        let projection = create_projection(fov);
         self.world_shader.set_mat4("u_view", player_v);
+        self.world_shader.set_mat4("u_projection", projection);
+        self.skybox.set_projection(projection);
+        self.world_shader.set_i32("u_underwater", underwater as i32);
         gl::Viewport(
             0,
             0,
             DRAW_FRAMEBUFFER_SIZE.0 as i32,
             DRAW_FRAMEBUFFER_SIZE.1 as i32,
         );
+        if underwater {
+            // The sky isn't visible from under the water, everything beyond the fog is water
+            gl::ClearColor(WATER_FOG_COLOR.0, WATER_FOG_COLOR.1, WATER_FOG_COLOR.2, 1.0);
+        }
         gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl::ClearColor(CLEAR_COLOR.0, CLEAR_COLOR.1, CLEAR_COLOR.2, 1.0);
 
         // Setting the pointlight cubemap for rendering the world
         gl::ActiveTexture(gl::TEXTURE1);
         gl::BindTexture(gl::TEXTURE_CUBE_MAP_ARRAY, self.point_light_cubemap_handle);
 
+        let selected_object = self.selection.map(|s| s.object_index);
         for obj in static_objects {
+            let mut tint = obj.material.tint();
+            if Some(obj.object_index) == selected_object {
+                for (channel, selected_channel) in tint.iter_mut().zip(SELECTED_TINT.iter()) {
+                    *channel *= selected_channel;
+                }
+            }
             self.world_shader.set_mat4("u_model", obj.transform);
+            self.world_shader
+                .set_vec4("u_tint", tint[0], tint[1], tint[2], tint[3]);
             obj.material.draw();
         }
 
         // Fill the depth==1 fragments with sky texture
-        self.skybox.draw(player_v);
+        if !underwater {
+            self.skybox.draw(player_v);
+        }
+
+        // Translucent, so they go after everything opaque
+        self.world_shader.set_used();
+        if let Some((player_position, player_forward)) = player {
+            self.capsule_model.draw(
+                &self.world_shader,
+                player_position,
+                player_forward,
+                PLAYER_COLOR,
+            );
+        }
+        if let Some((ghost_position, ghost_forward)) = ghost {
+            self.capsule_model.draw(
+                &self.world_shader,
+                ghost_position,
+                ghost_forward,
+                GHOST_COLOR,
+            );
+        }
+
+        // Drawn over everything, so that it can be grabbed even from inside a wall
+        if let Some(selection) = self.selection {
+            gl::Disable(gl::DEPTH_TEST);
+            self.draw_gizmo(&selection);
+            gl::Enable(gl::DEPTH_TEST);
+        }
 
         // Render from the draw framebuffer to the default framebuffer (the screen)
         gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.draw_fbo);
@@ -199,11 +294,49 @@ impl Renderer {
         );
     }
 
+    // One capsule along each axis, the one being edited is opaque
+    unsafe fn draw_gizmo(&self, selection: &editor::Selection) {
+        let axes = [
+            (Vector3::unit_x(), Matrix4::from_angle_z(Deg(-90.0))),
+            (Vector3::unit_y(), Matrix4::identity()),
+            (Vector3::unit_z(), Matrix4::from_angle_x(Deg(90.0))),
+        ];
+        for (axis_index, (axis, rotation)) in axes.iter().enumerate() {
+            let center = selection.position + axis * GIZMO_LENGTH * 0.5;
+            let transform = Matrix4::from_translation(center.to_vec())
+                * rotation
+                * Matrix4::from_nonuniform_scale(
+                    GIZMO_THICKNESS,
+                    GIZMO_LENGTH * 0.5,
+                    GIZMO_THICKNESS,
+                );
+            let alpha = if axis_index == selection.axis {
+                1.0
+            } else {
+                GIZMO_INACTIVE_ALPHA
+            };
+            self.capsule_model.draw_transformed(
+                &self.world_shader,
+                &[transform],
+                (axis.x, axis.y, axis.z, alpha),
+            );
+        }
+    }
+
     pub fn finish_render(&mut self) {
         self.window.gl_swap_window();
     }
 }
 
+fn create_projection(fov: f32) -> Matrix4<f32> {
+    cgmath::perspective(
+        cgmath::Deg(fov),
+        SCREEN_SIZE.0 as f32 / SCREEN_SIZE.1 as f32,
+        NEAR_PLANE,
+        FAR_PLANE,
+    )
+}
+
 unsafe fn create_draw_backbuffer() -> u32 {
     let mut draw_fbo = 0;
     gl::GenFramebuffers(1, &mut draw_fbo);
@@ -258,3 +391,24 @@ unsafe fn create_draw_backbuffer() -> u32 {
 
     draw_fbo
 }
+
+unsafe fn set_point_light_uniforms(world_shader: &Shader, point_lights: &[PointLight]) {
+    world_shader.set_i32("u_point_light_count", point_lights.len() as i32);
+
+    for (i, point_light) in point_lights.iter().enumerate() {
+        world_shader.set_vec3(
+            format!("u_point_lights[{}].position", i).as_str(),
+            point_light.position.x,
+            point_light.position.y,
+            point_light.position.z,
+        );
+        world_shader.set_f32(
+            format!("u_point_lights[{}].intensity", i).as_str(),
+            point_light.intensity,
+        );
+        world_shader.set_f32(
+            format!("u_point_lights[{}].attenuation", i).as_str(),
+            point_light.attenuation,
+        );
+    }
+}
@@ -1,50 +1,101 @@
 use crate::render;
+use crate::vfs::Vfs;
 use cgmath::{Matrix, Matrix4};
 use gl;
 use gl::types::*;
 use std;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::prelude::*;
+use std::path::Path;
 
 const VERSION: &'static str = "#version 420 core\r\n";
 const DEFINE_VERTEX: &'static str = "#define VERTEX\r\n";
 const DEFINE_FRAGMENT: &'static str = "#define FRAGMENT\r\n";
 const DEFINE_GEOMETRY: &'static str = "#define GEOMETRY\r\n";
 
+#[derive(Clone, Copy)]
+enum Uniform {
+    Mat4(Matrix4<f32>),
+    I32(i32),
+    F32(f32),
+    Vec3(f32, f32, f32),
+    Vec4(f32, f32, f32, f32),
+}
+
 pub struct Shader {
-    id: u32,
+    id: Cell<u32>, // Replaced by a reload, which can happen while the shader is shared
+    path: String,
+    has_geom: bool,
+    uniforms: RefCell<HashMap<String, Uniform>>, // Last values set, re-applied to a reloaded program
 }
 
 impl Shader {
-    pub fn from_file(path: &str, has_geom: bool) -> Result<Shader, String> {
-        let mut shader_file =
-            File::open(path).expect(format!("Unable to open shader file: {}", path).as_str());
-        let mut shader_text = String::new();
-        shader_file
-            .read_to_string(&mut shader_text)
-            .expect("Unable to read shader file");
-
-        let shader_id = unsafe { gl::CreateProgram() };
-        let vert_id = Shader::from_source(&shader_text, gl::VERTEX_SHADER, DEFINE_VERTEX)?;
-        let frag_id = Shader::from_source(&shader_text, gl::FRAGMENT_SHADER, DEFINE_FRAGMENT)?;
-        let geom_id = {
-            if has_geom {
-                Shader::from_source(&shader_text, gl::GEOMETRY_SHADER, DEFINE_GEOMETRY)?
-            } else {
-                0
+    pub fn from_file(path: &str, has_geom: bool, vfs: &Vfs) -> Result<Shader, String> {
+        Ok(Shader {
+            id: Cell::new(Shader::compile(path, has_geom, vfs)?),
+            path: path.to_string(),
+            has_geom,
+            uniforms: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        Path::new(&self.path)
+    }
+
+    // A failing compile keeps the previous program running, the error is only returned
+    pub fn reload(&self, vfs: &Vfs) -> Result<(), String> {
+        let id = Shader::compile(&self.path, self.has_geom, vfs)?;
+        unsafe {
+            gl::DeleteProgram(self.id.get());
+        }
+        self.id.set(id);
+        for (name, uniform) in self.uniforms.borrow().iter() {
+            unsafe {
+                self.apply_uniform(name, *uniform);
+            }
+        }
+        Ok(())
+    }
+
+    fn compile(path: &str, has_geom: bool, vfs: &Vfs) -> Result<GLuint, String> {
+        let shader_text = vfs
+            .read_to_string(path)
+            .map_err(|e| format!("Unable to read shader file {}: {}", path, e))?;
+
+        let mut stages = vec![
+            (gl::VERTEX_SHADER, DEFINE_VERTEX),
+            (gl::FRAGMENT_SHADER, DEFINE_FRAGMENT),
+        ];
+        if has_geom {
+            stages.push((gl::GEOMETRY_SHADER, DEFINE_GEOMETRY));
+        }
+        let mut stage_ids = Vec::new();
+        for (shader_type, define) in stages {
+            match Shader::from_source(&shader_text, shader_type, define) {
+                Ok(stage_id) => stage_ids.push(stage_id),
+                Err(error) => {
+                    unsafe {
+                        delete_stages(0, &stage_ids);
+                    }
+                    return Err(error);
+                }
             }
-        };
+        }
+
+        // Only created once every stage compiled, so that a broken save leaves nothing behind
         unsafe {
+            let shader_id = gl::CreateProgram();
             let mut success: GLint = 1;
-            gl::AttachShader(shader_id, vert_id);
-            gl::AttachShader(shader_id, frag_id);
-            if has_geom {
-                gl::AttachShader(shader_id, geom_id);
+            for stage_id in &stage_ids {
+                gl::AttachShader(shader_id, *stage_id);
             }
 
             gl::LinkProgram(shader_id);
             gl::GetProgramiv(shader_id, gl::LINK_STATUS, &mut success);
+            // The program keeps what it needs from the stages, linked or not
+            delete_stages(shader_id, &stage_ids);
 
             if success == 0 {
                 let mut len: i32 = 0;
@@ -58,17 +109,12 @@ impl Shader {
                     error.as_ptr() as *mut GLchar,
                 );
 
+                gl::DeleteProgram(shader_id);
                 return Err(error.to_string_lossy().into_owned());
             }
 
-            gl::DetachShader(shader_id, vert_id);
-            gl::DetachShader(shader_id, frag_id);
-            if has_geom {
-                gl::DetachShader(shader_id, geom_id);
-            }
+            Ok(shader_id)
         }
-
-        Ok(Shader { id: shader_id })
     }
 
     fn from_source(source: &str, shader_type: GLenum, define: &str) -> Result<GLuint, String> {
@@ -89,6 +135,7 @@ impl Shader {
                 let error = create_whitespace_cstring_with_len(len as usize);
                 gl::GetShaderInfoLog(id, len, std::ptr::null_mut(), error.as_ptr() as *mut GLchar);
 
+                gl::DeleteShader(id);
                 return Err(error.to_string_lossy().into_owned());
             }
         }
@@ -97,55 +144,85 @@ impl Shader {
     }
 
     pub unsafe fn set_used(&self) {
-        gl::UseProgram(self.id);
+        gl::UseProgram(self.id.get());
     }
 
     pub unsafe fn set_mat4(&self, name: &str, matrix: Matrix4<f32>) {
-        let cstr = CString::new(name).unwrap();
-        let loc = gl::GetUniformLocation(self.id, cstr.as_ptr());
-
-        gl::ProgramUniformMatrix4fv(self.id, loc, 1, gl::FALSE, matrix.as_ptr());
-        render::check_gl_error(format!("{} mat4", name).as_str());
+        self.set_uniform(name, Uniform::Mat4(matrix));
     }
 
     pub unsafe fn set_i32(&self, name: &str, i: i32) {
-        let cstr = CString::new(name).unwrap();
-        let loc = gl::GetUniformLocation(self.id, cstr.as_ptr());
-
-        gl::Uniform1i(loc, i);
-        render::check_gl_error(format!("{} i32", name).as_str());
+        self.set_uniform(name, Uniform::I32(i));
     }
 
     pub unsafe fn set_f32(&self, name: &str, f: f32) {
-        let cstr = CString::new(name).unwrap();
-        let loc = gl::GetUniformLocation(self.id, cstr.as_ptr());
-
-        gl::Uniform1f(loc, f);
-        render::check_gl_error(format!("{} f32", name).as_str());
+        self.set_uniform(name, Uniform::F32(f));
     }
 
     pub unsafe fn set_vec3(&self, name: &str, f0: f32, f1: f32, f2: f32) {
-        let cstr = CString::new(name).unwrap();
-        let loc = gl::GetUniformLocation(self.id, cstr.as_ptr());
-
-        gl::Uniform3f(loc, f0, f1, f2);
-        render::check_gl_error(format!("{} vec3", name).as_str());
+        self.set_uniform(name, Uniform::Vec3(f0, f1, f2));
     }
 
     pub unsafe fn set_vec4(&self, name: &str, f0: f32, f1: f32, f2: f32, f3: f32) {
+        self.set_uniform(name, Uniform::Vec4(f0, f1, f2, f3));
+    }
+
+    unsafe fn set_uniform(&self, name: &str, uniform: Uniform) {
+        // Most calls overwrite a known uniform, e.g. u_model for every object, so only new names allocate
+        let mut uniforms = self.uniforms.borrow_mut();
+        if let Some(value) = uniforms.get_mut(name) {
+            *value = uniform;
+        } else {
+            uniforms.insert(name.to_string(), uniform);
+        }
+        self.apply_uniform(name, uniform);
+    }
+
+    unsafe fn apply_uniform(&self, name: &str, uniform: Uniform) {
         let cstr = CString::new(name).unwrap();
-        let loc = gl::GetUniformLocation(self.id, cstr.as_ptr());
+        let id = self.id.get();
+        let loc = gl::GetUniformLocation(id, cstr.as_ptr());
 
-        gl::Uniform4f(loc, f0, f1, f2, f3);
-        render::check_gl_error(format!("{} vec4", name).as_str());
+        match uniform {
+            Uniform::Mat4(matrix) => {
+                gl::ProgramUniformMatrix4fv(id, loc, 1, gl::FALSE, matrix.as_ptr());
+                render::check_gl_error(format!("{} mat4", name).as_str());
+            }
+            Uniform::I32(i) => {
+                gl::ProgramUniform1i(id, loc, i);
+                render::check_gl_error(format!("{} i32", name).as_str());
+            }
+            Uniform::F32(f) => {
+                gl::ProgramUniform1f(id, loc, f);
+                render::check_gl_error(format!("{} f32", name).as_str());
+            }
+            Uniform::Vec3(f0, f1, f2) => {
+                gl::ProgramUniform3f(id, loc, f0, f1, f2);
+                render::check_gl_error(format!("{} vec3", name).as_str());
+            }
+            Uniform::Vec4(f0, f1, f2, f3) => {
+                gl::ProgramUniform4f(id, loc, f0, f1, f2, f3);
+                render::check_gl_error(format!("{} vec4", name).as_str());
+            }
+        }
     }
 }
 
 impl Drop for Shader {
     fn drop(&mut self) {
         unsafe {
-            gl::DeleteProgram(self.id);
+            gl::DeleteProgram(self.id.get());
+        }
+    }
+}
+
+// Detached first when they're attached to a program, which is 0 otherwise
+unsafe fn delete_stages(program_id: GLuint, stage_ids: &[GLuint]) {
+    for stage_id in stage_ids {
+        if program_id != 0 {
+            gl::DetachShader(program_id, *stage_id);
         }
+        gl::DeleteShader(*stage_id);
     }
 }
 
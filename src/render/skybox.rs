@@ -1,9 +1,11 @@
+use crate::asset_cache::AssetCache;
 use crate::render::shader::*;
 use crate::render::*;
 use crate::render::{BufferHandle, TextureHandle};
+use crate::vfs::Vfs;
 use cgmath::*;
 use image::GenericImageView;
-use std::path::Path;
+use std::rc::Rc;
 
 const VERTEX_DATA: [f32; 108] = [
     -1.0, 1.0, -1.0, -1.0, -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0,
@@ -18,16 +20,17 @@ const VERTEX_DATA: [f32; 108] = [
 pub struct Skybox {
     vao: BufferHandle,
     cubemap_handle: TextureHandle,
-    shader: Shader,
+    shader: Rc<Shader>,
 }
 
 impl Skybox {
-    pub fn new(projection: Matrix4<f32>) -> Skybox {
+    pub fn new(projection: Matrix4<f32>, cache: &mut AssetCache) -> Skybox {
         let mut vao = 0;
         let mut vbo = 0;
         let cubemap_handle: TextureHandle;
 
-        let shader = Shader::from_file("src/shaders/skybox.glsl", false)
+        let shader = cache
+            .shader("src/shaders/skybox.glsl", false)
             .expect("Problem loading skybox shader");
 
         unsafe {
@@ -54,7 +57,7 @@ impl Skybox {
                 std::ptr::null(),
             );
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            cubemap_handle = load_cubemap_from_file("assets/skybox/gehenna");
+            cubemap_handle = load_cubemap_from_file("assets/skybox/gehenna", cache.vfs());
             check_gl_error("skybox2");
 
             shader.set_used();
@@ -69,6 +72,10 @@ impl Skybox {
         }
     }
 
+    pub unsafe fn set_projection(&self, projection: Matrix4<f32>) {
+        self.shader.set_mat4("u_projection", projection);
+    }
+
     pub unsafe fn draw(&mut self, player_v: Matrix4<f32>) {
         self.shader.set_used();
 
@@ -84,7 +91,7 @@ impl Skybox {
     }
 }
 
-unsafe fn load_cubemap_from_file(cubemap_path: &str) -> TextureHandle {
+unsafe fn load_cubemap_from_file(cubemap_path: &str, vfs: &Vfs) -> TextureHandle {
     let mut cubemap_handle = 0;
 
     gl::GenTextures(1, &mut cubemap_handle);
@@ -93,26 +100,32 @@ unsafe fn load_cubemap_from_file(cubemap_path: &str) -> TextureHandle {
     load_cubemap_face(
         format!("{}_front.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_POSITIVE_Z,
+        vfs,
     );
     load_cubemap_face(
         format!("{}_back.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_NEGATIVE_Z,
+        vfs,
     );
     load_cubemap_face(
         format!("{}_left.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_NEGATIVE_X,
+        vfs,
     );
     load_cubemap_face(
         format!("{}_right.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+        vfs,
     );
     load_cubemap_face(
         format!("{}_top.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_POSITIVE_Y,
+        vfs,
     );
     load_cubemap_face(
         format!("{}_bottom.png", cubemap_path).as_str(),
         gl::TEXTURE_CUBE_MAP_NEGATIVE_Y,
+        vfs,
     );
 
     gl::TexParameteri(
@@ -144,8 +157,11 @@ unsafe fn load_cubemap_from_file(cubemap_path: &str) -> TextureHandle {
     cubemap_handle
 }
 
-fn load_cubemap_face(cubemap_face_path: &str, texture_enum: u32) {
-    let img = image::open(&Path::new(&cubemap_face_path))
+fn load_cubemap_face(cubemap_face_path: &str, texture_enum: u32, vfs: &Vfs) {
+    let img = vfs
+        .read(cubemap_face_path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| image::load_from_memory(&bytes).map_err(|e| e.to_string()))
         .expect(format!("Error loading the cubemap face {:?}", cubemap_face_path).as_str());
     // let img = img.flipv();
     if img.color() != image::ColorType::Rgba8 {
@@ -1,9 +1,68 @@
+use crate::asset_error::{AssetError, AssetErrorCause};
 use crate::render::TextureHandle;
+use crate::vfs::Vfs;
 use gl::types::*;
-use image::{GenericImageView};
-use std::path::Path;
 
-pub fn load_from_file(texture_path: &str) -> TextureHandle {
+// An uploaded 2D texture, deleted from the GPU once dropped
+#[derive(Debug)]
+pub struct Texture {
+    handle: TextureHandle,
+    bytes: usize, // Including the mipmaps
+}
+
+impl Texture {
+    pub fn handle(&self) -> TextureHandle {
+        self.handle
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // Never uploaded, for tests without a GL context. It has to be leaked, dropping it calls into GL.
+    #[cfg(test)]
+    pub fn placeholder() -> Texture {
+        Texture {
+            handle: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn white() -> Texture {
+        Texture {
+            handle: unsafe { create_white_texture() },
+            bytes: 4,
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.handle);
+        }
+    }
+}
+
+pub fn load_from_file(texture_path: &str, vfs: &Vfs) -> Result<Texture, AssetError> {
+    let bytes = vfs
+        .read(texture_path)
+        .map_err(|e| AssetError::from_io(texture_path, &e))?;
+    load_from_memory(&bytes, texture_path)
+}
+
+// For images embedded in another file, name is only used for error messages
+pub fn load_from_memory(bytes: &[u8], name: &str) -> Result<Texture, AssetError> {
+    let img = image::load_from_memory(bytes)
+        .map_err(|e| AssetError::new(name, AssetErrorCause::Image(e.to_string())))?;
+    Ok(upload(img))
+}
+
+// Converted to RGBA8 first, so that e.g. JPEGs and RGB PNGs exported from Blender work as they are
+fn upload(img: image::DynamicImage) -> Texture {
+    let img = img.flipv().to_rgba8();
+    let img_data = img.as_raw();
+
     let mut texture_handle = 0;
     unsafe {
         gl::GenTextures(1, &mut texture_handle);
@@ -13,18 +72,6 @@ pub fn load_from_file(texture_path: &str) -> TextureHandle {
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-
-        let img = image::open(&Path::new(&texture_path))
-            .expect(format!("Error loading the texture {:?}", texture_path).as_str());
-        let img = img.flipv();
-        let img_data = img.to_bytes();
-        if img.color() != image::ColorType::Rgba8 {
-            panic!(
-                "Image channels isn't RGBA8, instead {:?} for image file {:?}",
-                img.color(),
-                texture_path
-            );
-        }
         gl::TexImage2D(
             gl::TEXTURE_2D,
             0,
@@ -38,5 +85,81 @@ pub fn load_from_file(texture_path: &str) -> TextureHandle {
         );
         gl::GenerateMipmap(gl::TEXTURE_2D);
     }
-    texture_handle
+    // A full mipmap chain adds a third on top of the base level
+    let bytes = img_data.len() * 4 / 3;
+    Texture {
+        handle: texture_handle,
+        bytes,
+    }
+}
+
+// RGBA8 levels that are already flipped and downsampled, e.g. by the cooker, so nothing is generated here
+pub fn from_mips(
+    width: u32,
+    height: u32,
+    levels: &[&[u8]],
+    name: &str,
+) -> Result<Texture, AssetError> {
+    let expected_levels = 32 - width.max(height).leading_zeros() as usize;
+    let is_complete = levels.len() == expected_levels
+        && levels.iter().enumerate().all(|(i, level)| {
+            let (level_width, level_height) = ((width >> i).max(1), (height >> i).max(1));
+            level.len() == (level_width * level_height * 4) as usize
+        });
+    if !is_complete {
+        return Err(AssetError::new(
+            name,
+            AssetErrorCause::Image("incomplete mipmap chain".to_string()),
+        ));
+    }
+
+    let mut texture_handle = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture_handle);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, texture_handle);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        for (i, level) in levels.iter().enumerate() {
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                i as i32,
+                gl::RGBA as i32,
+                (width >> i).max(1) as i32,
+                (height >> i).max(1) as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                level.as_ptr() as *const GLvoid,
+            );
+        }
+    }
+    Ok(Texture {
+        handle: texture_handle,
+        bytes: levels.iter().map(|l| l.len()).sum(),
+    })
+}
+
+// 1x1 opaque white, for things that are only colored by a tint
+pub unsafe fn create_white_texture() -> TextureHandle {
+    let mut texture = 0;
+    let pixel: [u8; 4] = [255, 255, 255, 255];
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGBA as i32,
+        1,
+        1,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        pixel.as_ptr() as *const GLvoid,
+    );
+    texture
 }
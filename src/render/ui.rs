@@ -1,33 +1,57 @@
+use crate::asset_cache::AssetCache;
+use crate::bindings_menu::BindingsMenu;
 use crate::geom::*;
+use crate::input::*;
 use crate::player::Player;
 use crate::render::shader::*;
 use crate::render::texture;
 use crate::render::ui_batch::*;
 use crate::render::*;
+use crate::run_timer::*;
 use cgmath::*;
 use gl::types::*;
 use image::{DynamicImage, Rgba};
 use rusttype::{point, Font, Scale};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const TEXT_COLOR: (u8, u8, u8) = (255, 0, 255);
+const AHEAD_COLOR: (u8, u8, u8) = (0, 255, 64);
+const BEHIND_COLOR: (u8, u8, u8) = (255, 64, 0);
+const SELECTED_COLOR: (u8, u8, u8) = (255, 255, 0);
+const FLASH_DURATION: f32 = 250.0; // Milliseconds
+const FLASH_MAX_ALPHA: f32 = 0.8;
+const SHADER_ERROR_MAX_LINES: usize = 20;
+const SHADER_ERROR_MAX_CHARS: usize = 120;
 
 pub struct Ui<'a> {
     batches: Vec<Batch>,
-    shader: Shader,
+    shader: Rc<Shader>,
     font: Font<'a>,
+    flash_batch: Batch,
+    flash_timer: f32,
+    shader_errors: BTreeMap<PathBuf, String>, // Compile logs of the shaders that failed to reload
 }
 
 impl Ui<'_> {
-    pub fn init() -> Self {
+    pub fn init(cache: &mut AssetCache) -> Self {
         let font_data = include_bytes!("../../assets/RobotoMono-Regular.ttf");
         let font = Font::try_from_bytes(font_data as &[u8]).expect("Error constructing Font");
-        let _texture1 = texture::load_from_file("assets/prototype.png");
-        let texture2 = create_from_text("Progress", 32.0, &font);
+        let _texture1 = cache.texture("assets/prototype.png");
+        let (texture2, _) = create_from_text("Progress", 32.0, TEXT_COLOR, &font);
 
-        let shader =
-            Shader::from_file("src/shaders/ui.glsl", false).expect("Error loading ui shader");
+        let shader = cache
+            .shader("src/shaders/ui.glsl", false)
+            .expect("Error loading ui shader");
 
         let _rekt1 = Rect::new(-0.01, 0.01, 0.02, 0.02);
         let rekt2 = Rect::new(-0.4, -0.9, 0.2, 0.1);
 
+        // Covers the whole screen in NDC
+        let flash_texture = unsafe { texture::create_white_texture() };
+        let flash_batch = Batch::new(vec![Rect::new(-1.0, 1.0, 2.0, 2.0)], flash_texture, false);
+
         let batches = vec![
             // Batch::new(vec![rekt1], texture1, false),
             Batch::new(vec![rekt2], texture2, false),
@@ -42,33 +66,181 @@ impl Ui<'_> {
             batches: batches,
             shader: shader,
             font: font,
+            flash_batch,
+            flash_timer: 0.0,
+            shader_errors: BTreeMap::new(),
         }
     }
 
-    fn draw_text(&mut self, text: &str) {
-        let rect = Rect::new(-0.9, 0.9, 0.2, 0.2); // TODO: Provide this from the outside
-        let texture = create_from_text(text, 32.0, &self.font);
+    // A short white flash over everything, e.g. when teleporting
+    pub fn flash(&mut self) {
+        self.flash_timer = FLASH_DURATION;
+    }
+
+    // Failed shaders stay on screen until they compile again
+    pub fn show_shader_reloads(&mut self, reloads: &[(PathBuf, Result<(), String>)]) {
+        for (path, result) in reloads {
+            match result {
+                Ok(()) => {
+                    self.shader_errors.remove(path);
+                }
+                Err(log) => {
+                    self.shader_errors.insert(path.clone(), log.clone());
+                }
+            }
+        }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.flash_timer = (self.flash_timer - dt).max(0.0);
+    }
+
+    // Height is in NDC, the width follows the aspect ratio of the rendered text
+    fn draw_text(&mut self, text: &str, left: f32, top: f32, height: f32, color: (u8, u8, u8)) {
+        let (texture, aspect) = create_from_text(text, 32.0, color, &self.font);
+        let width = height * aspect * SCREEN_SIZE.1 as f32 / SCREEN_SIZE.0 as f32;
+        let rect = Rect::new(left, top, width, height);
 
         self.batches.push(Batch::new(vec![rect], texture, true));
     }
 
-    pub unsafe fn draw(&mut self, player: &Player) {
+    fn draw_run_timer(&mut self, run_timer: &RunTimer) {
+        if let Some(best) = run_timer.personal_best() {
+            let best_string = format!("PB {}", format_time(best.total_time));
+            self.draw_text(best_string.as_str(), 0.55, 0.95, 0.08, TEXT_COLOR);
+        }
+
+        if run_timer.state() == RunState::Idle {
+            return;
+        }
+
+        let time_string = format_time(run_timer.elapsed());
+        self.draw_text(time_string.as_str(), -0.12, 0.95, 0.12, TEXT_COLOR);
+
+        if let Some(delta) = run_timer.visible_delta() {
+            let color = if delta < 0.0 {
+                AHEAD_COLOR
+            } else {
+                BEHIND_COLOR
+            };
+            self.draw_text(format_delta(delta).as_str(), -0.06, 0.83, 0.08, color);
+        }
+    }
+
+    fn draw_bindings_menu(&mut self, menu: &BindingsMenu, bindings: &Bindings) {
+        const LINE_HEIGHT: f32 = 0.07;
+        let top = 0.6;
+
+        self.draw_text(
+            "Bindings: enter adds, backspace clears",
+            -0.6,
+            top,
+            LINE_HEIGHT,
+            TEXT_COLOR,
+        );
+
+        for (i, action) in ALL_ACTIONS.iter().enumerate() {
+            let is_selected = *action == menu.selected();
+            let binding_names = {
+                if is_selected && menu.is_capturing() {
+                    "press a key...".to_string()
+                } else if bindings.get(*action).is_empty() {
+                    "-".to_string()
+                } else {
+                    let names: Vec<String> =
+                        bindings.get(*action).iter().map(|b| (*b).into()).collect();
+                    names.join(", ")
+                }
+            };
+            let line = format!("{:?}: {}", action, binding_names);
+            let color = if is_selected {
+                SELECTED_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            let line_top = top - LINE_HEIGHT * (i + 1) as f32;
+            self.draw_text(line.as_str(), -0.6, line_top, LINE_HEIGHT, color);
+        }
+    }
+
+    fn draw_shader_errors(&mut self) {
+        const LINE_HEIGHT: f32 = 0.05;
+        let top = 0.6;
+
+        let lines: Vec<String> = self
+            .shader_errors
+            .iter()
+            .flat_map(|(path, log)| {
+                let header = format!("{} failed to compile:", path.display());
+                let log_lines = log
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.chars().take(SHADER_ERROR_MAX_CHARS).collect());
+                std::iter::once(header).chain(log_lines)
+            })
+            .take(SHADER_ERROR_MAX_LINES)
+            .collect();
+
+        for (i, line) in lines.iter().enumerate() {
+            let line_top = top - LINE_HEIGHT * i as f32;
+            self.draw_text(line.as_str(), -0.98, line_top, LINE_HEIGHT, BEHIND_COLOR);
+        }
+    }
+
+    pub unsafe fn draw(
+        &mut self,
+        player: &Player,
+        run_timer: &RunTimer,
+        bindings_menu: &BindingsMenu,
+        bindings: &Bindings,
+        demo_status: Option<&str>,
+        editor_status: &[String],
+    ) {
         self.shader.set_used();
 
         let velocity_string = format!("{:.3}", horz(&player.velocity).magnitude());
-        self.draw_text(velocity_string.as_str());
+        self.draw_text(velocity_string.as_str(), -0.9, 0.9, 0.2, TEXT_COLOR);
+        let state_string = format!("{:?}", player.state());
+        self.draw_text(state_string.as_str(), -0.9, 0.7, 0.06, TEXT_COLOR);
+        self.draw_run_timer(run_timer);
+        if let Some(status) = demo_status {
+            self.draw_text(status, 0.7, -0.85, 0.08, BEHIND_COLOR);
+        }
+        for (i, line) in editor_status.iter().enumerate() {
+            let line_top = 0.6 - 0.06 * i as f32;
+            self.draw_text(line.as_str(), -0.9, line_top, 0.06, SELECTED_COLOR);
+        }
+        if bindings_menu.is_open() {
+            self.draw_bindings_menu(bindings_menu, bindings);
+        }
+        if !self.shader_errors.is_empty() {
+            self.draw_shader_errors();
+        }
 
         gl::Viewport(0, 0, SCREEN_SIZE.0 as i32, SCREEN_SIZE.1 as i32);
+        self.shader.set_vec4("u_tint", 1.0, 1.0, 1.0, 1.0);
         for batch in self.batches.iter() {
             batch.draw();
         }
+
+        if self.flash_timer > 0.0 {
+            let alpha = self.flash_timer / FLASH_DURATION * FLASH_MAX_ALPHA;
+            self.shader.set_vec4("u_tint", 1.0, 1.0, 1.0, alpha);
+            self.flash_batch.draw();
+        }
         self.batches.retain(|b| !b.draw_single_frame);
     }
 }
 
-fn create_from_text(content: &str, size: f32, font: &Font) -> TextureHandle {
+// Returns the texture and its width/height ratio
+fn create_from_text(
+    content: &str,
+    size: f32,
+    color: (u8, u8, u8),
+    font: &Font,
+) -> (TextureHandle, f32) {
     let scale = Scale::uniform(size);
-    let color = (255, 0, 255);
     let v_metrics = font.v_metrics(scale);
     let glyphs: Vec<_> = font
         .layout(content, scale, point(20.0, 20.0 + v_metrics.ascent))
@@ -100,6 +272,7 @@ fn create_from_text(content: &str, size: f32, font: &Font) -> TextureHandle {
         }
     }
 
+    let aspect = img.width() as f32 / img.height() as f32;
     let mut texture = 0;
     unsafe {
         gl::GenTextures(1, &mut texture);
@@ -126,5 +299,5 @@ fn create_from_text(content: &str, size: f32, font: &Font) -> TextureHandle {
         );
         gl::GenerateMipmap(gl::TEXTURE_2D);
     }
-    texture
+    (texture, aspect)
 }
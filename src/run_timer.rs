@@ -0,0 +1,268 @@
+use crate::trigger::{TriggerEvent, TriggerKind};
+use crate::vfs::Vfs;
+use serde::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const RECORDS_DIR: &str = "records";
+const SPLIT_DELTA_SHOW_TIME: f32 = 3000.0; // How long a split delta stays on the HUD
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub total_time: f32,
+    pub split_times: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunEvent {
+    Started,
+    Finished { is_new_best: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunState {
+    Idle,
+    Running,
+    Finished,
+}
+
+pub struct RunTimer {
+    state: RunState,
+    elapsed: f32,
+    split_times: Vec<f32>,
+    split_names: Vec<String>,
+    last_delta: Option<f32>,
+    last_delta_time: f32,
+    personal_best: Option<RunRecord>,
+    records_path: Option<PathBuf>,
+}
+
+impl RunTimer {
+    pub fn new(personal_best: Option<RunRecord>, records_path: Option<PathBuf>) -> RunTimer {
+        RunTimer {
+            state: RunState::Idle,
+            elapsed: 0.0,
+            split_times: Vec::new(),
+            split_names: Vec::new(),
+            last_delta: None,
+            last_delta_time: 0.0,
+            personal_best,
+            records_path,
+        }
+    }
+
+    // Personal bests are kept with the game data, one file per level
+    pub fn for_level(level_name: &str, vfs: &Vfs) -> RunTimer {
+        let records_path = Path::new(RECORDS_DIR).join(format!("{}.json", level_name));
+        let personal_best = vfs
+            .read_to_string(&records_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        RunTimer::new(personal_best, Some(records_path))
+    }
+
+    pub fn on_trigger(
+        &mut self,
+        kind: &TriggerKind,
+        name: &str,
+        event: TriggerEvent,
+        vfs: &Vfs,
+    ) -> Option<RunEvent> {
+        match (kind, event) {
+            (TriggerKind::Start, TriggerEvent::Entered) => {
+                self.reset();
+                None
+            }
+            // The clock starts when leaving the start zone, not when entering it
+            (TriggerKind::Start, TriggerEvent::Exited) => {
+                self.start();
+                Some(RunEvent::Started)
+            }
+            (TriggerKind::Split, TriggerEvent::Entered) => {
+                self.split(name);
+                None
+            }
+            (TriggerKind::Finish, TriggerEvent::Entered) => self.finish(vfs),
+            _ => None,
+        }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        if self.state == RunState::Running {
+            self.elapsed += dt;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = RunState::Idle;
+        self.elapsed = 0.0;
+        self.split_times.clear();
+        self.split_names.clear();
+        self.last_delta = None;
+    }
+
+    fn start(&mut self) {
+        self.reset();
+        self.state = RunState::Running;
+    }
+
+    fn split(&mut self, name: &str) {
+        if self.state != RunState::Running || self.split_names.iter().any(|n| n == name) {
+            return;
+        }
+
+        let index = self.split_times.len();
+        self.split_times.push(self.elapsed);
+        self.split_names.push(name.to_owned());
+        self.last_delta = self
+            .personal_best
+            .as_ref()
+            .and_then(|best| best.split_times.get(index))
+            .map(|best_split| self.elapsed - best_split);
+        self.last_delta_time = self.elapsed;
+    }
+
+    fn finish(&mut self, vfs: &Vfs) -> Option<RunEvent> {
+        if self.state != RunState::Running {
+            return None;
+        }
+
+        self.state = RunState::Finished;
+        self.last_delta = self
+            .personal_best
+            .as_ref()
+            .map(|best| self.elapsed - best.total_time);
+        self.last_delta_time = self.elapsed;
+
+        let is_new_best = match self.last_delta {
+            Some(delta) => delta < 0.0,
+            None => true,
+        };
+        if is_new_best {
+            let record = RunRecord {
+                total_time: self.elapsed,
+                split_times: self.split_times.clone(),
+            };
+            self.save_record(&record, vfs);
+            self.personal_best = Some(record);
+        }
+
+        Some(RunEvent::Finished { is_new_best })
+    }
+
+    fn save_record(&self, record: &RunRecord, vfs: &Vfs) {
+        if let Some(path) = &self.records_path {
+            let json = serde_json::to_string_pretty(record).expect("Run record serialization");
+            if let Err(e) = write_record(path, json.as_bytes(), vfs) {
+                println!("Couldn't save the personal best to {:?}: {}", path, e);
+            }
+        }
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    // The delta of the latest split (or the finish) against the personal best
+    pub fn visible_delta(&self) -> Option<f32> {
+        match self.state {
+            RunState::Idle => None,
+            RunState::Finished => self.last_delta,
+            RunState::Running => self
+                .last_delta
+                .filter(|_| self.elapsed - self.last_delta_time < SPLIT_DELTA_SHOW_TIME),
+        }
+    }
+
+    pub fn personal_best(&self) -> Option<&RunRecord> {
+        self.personal_best.as_ref()
+    }
+}
+
+pub fn format_time(ms: f32) -> String {
+    let total_ms = ms.max(0.0).round() as u64;
+    format!(
+        "{}:{:02}.{:03}",
+        total_ms / 60000,
+        (total_ms / 1000) % 60,
+        total_ms % 1000
+    )
+}
+
+pub fn format_delta(ms: f32) -> String {
+    let sign = if ms < 0.0 { "-" } else { "+" };
+    format!("{}{:.2}", sign, ms.abs() / 1000.0)
+}
+
+// Records are written where the game data reads them from, see Vfs::writable_path
+pub fn write_record(path: &Path, bytes: &[u8], vfs: &Vfs) -> io::Result<()> {
+    let file_path = vfs.writable_path(path)?;
+    if let Some(dir) = file_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(file_path, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(timer: &mut RunTimer, split_at: f32, finish_at: f32) {
+        let vfs = &Vfs::new();
+        timer.on_trigger(&TriggerKind::Start, "start", TriggerEvent::Entered, vfs);
+        timer.on_trigger(&TriggerKind::Start, "start", TriggerEvent::Exited, vfs);
+        timer.tick(split_at);
+        timer.on_trigger(&TriggerKind::Split, "split", TriggerEvent::Entered, vfs);
+        timer.on_trigger(&TriggerKind::Split, "split", TriggerEvent::Entered, vfs);
+        timer.tick(finish_at - split_at);
+        let event = timer.on_trigger(&TriggerKind::Finish, "finish", TriggerEvent::Entered, vfs);
+        assert_eq!(event, Some(RunEvent::Finished { is_new_best: true }));
+    }
+
+    #[test]
+    fn test_first_run_becomes_personal_best() {
+        let mut timer = RunTimer::new(None, None);
+        run(&mut timer, 1000.0, 2500.0);
+
+        assert_eq!(timer.state(), RunState::Finished);
+        assert_eq!(
+            timer.personal_best(),
+            Some(&RunRecord {
+                total_time: 2500.0,
+                split_times: vec![1000.0],
+            })
+        );
+    }
+
+    #[test]
+    fn test_deltas_against_personal_best() {
+        let best = RunRecord {
+            total_time: 2000.0,
+            split_times: vec![1200.0],
+        };
+        let mut timer = RunTimer::new(Some(best.clone()), None);
+        let vfs = &Vfs::new();
+
+        timer.on_trigger(&TriggerKind::Start, "start", TriggerEvent::Exited, vfs);
+        timer.tick(1000.0);
+        timer.on_trigger(&TriggerKind::Split, "split", TriggerEvent::Entered, vfs);
+        assert_eq!(timer.visible_delta(), Some(-200.0));
+
+        timer.tick(1500.0);
+        timer.on_trigger(&TriggerKind::Finish, "finish", TriggerEvent::Entered, vfs);
+        assert_eq!(timer.visible_delta(), Some(500.0));
+        assert_eq!(timer.personal_best(), Some(&best));
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_time(83456.0), "1:23.456");
+        assert_eq!(format_delta(-1234.0), "-1.23");
+        assert_eq!(format_delta(500.0), "+0.50");
+    }
+}
@@ -0,0 +1,142 @@
+use crate::vfs::Vfs;
+use serde::*;
+
+// Missing fields (or a missing file) fall back to the defaults below
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub camera: CameraSettings,
+    pub look: LookSettings,
+    pub gamepad: GamepadSettings,
+    pub log_player_events: bool, // Writes jumps, landings etc. to records/player_events.log
+    pub hot_reload: HotReloadSettings,
+    pub movement: MovementSettings,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub fov: f32, // Vertical, in degrees
+    pub speed_fov_enabled: bool,
+    pub speed_fov_extra: f32, // Degrees added at full speed
+    pub speed_fov_min_speed: f32,
+    pub speed_fov_max_speed: f32,
+    pub view_bob_enabled: bool,
+    pub view_bob_amplitude: f32,
+    pub view_bob_frequency: f32, // Radians per unit travelled
+    pub landing_dip_enabled: bool,
+    pub landing_dip_scale: f32, // Dip distance per unit of impact speed
+    pub landing_dip_max: f32,
+    pub strafe_roll_enabled: bool,
+    pub strafe_roll_angle: f32, // Degrees at full strafe speed
+}
+
+impl Default for CameraSettings {
+    fn default() -> CameraSettings {
+        CameraSettings {
+            fov: 45.0,
+            speed_fov_enabled: true,
+            speed_fov_extra: 10.0,
+            speed_fov_min_speed: 0.01,
+            speed_fov_max_speed: 0.03,
+            view_bob_enabled: true,
+            view_bob_amplitude: 0.05,
+            view_bob_frequency: 1.3,
+            landing_dip_enabled: true,
+            landing_dip_scale: 15.0,
+            landing_dip_max: 0.3,
+            strafe_roll_enabled: true,
+            strafe_roll_angle: 1.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookSettings {
+    pub sensitivity: f32, // Degrees per mouse count
+    pub invert_y: bool,
+    pub raw_input: bool,   // Off lets the OS pointer acceleration through
+    pub acceleration: f32, // Extra sensitivity per count/ms of mouse speed, 0 disables it
+}
+
+impl Default for LookSettings {
+    fn default() -> LookSettings {
+        LookSettings {
+            sensitivity: 0.229,
+            invert_y: false,
+            raw_input: true,
+            acceleration: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    pub deadzone: f32,         // Fraction of the stick range that is ignored
+    pub look_sensitivity: f32, // Degrees per ms at full deflection
+    pub look_curve: f32,       // Exponent applied to the look stick
+    pub invert_look_y: bool,
+    pub trigger_threshold: f32, // How far a trigger is pulled before it counts as pressed
+    pub jump: String,           // SDL button name, or "lefttrigger"/"righttrigger"
+    pub crouch: String,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> GamepadSettings {
+        GamepadSettings {
+            deadzone: 0.15,
+            look_sensitivity: 0.18,
+            look_curve: 2.0,
+            invert_look_y: false,
+            trigger_threshold: 0.5,
+            jump: "a".to_string(),
+            crouch: "b".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotReloadSettings {
+    pub enabled: bool,
+    pub poll_interval: f32, // Milliseconds between checking the asset files
+}
+
+impl Default for HotReloadSettings {
+    fn default() -> HotReloadSettings {
+        HotReloadSettings {
+            enabled: true,
+            poll_interval: 500.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementSettings {
+    pub jump_buffer_window: f32, // Milliseconds a jump press is remembered before landing
+    pub coyote_time_window: f32, // Milliseconds the player can still jump after leaving ground
+}
+
+impl Default for MovementSettings {
+    fn default() -> MovementSettings {
+        MovementSettings {
+            jump_buffer_window: 120.0,
+            coyote_time_window: 100.0,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &str, vfs: &Vfs) -> Settings {
+        match vfs.read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!("Invalid settings file {}, using defaults: {}", path, e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+}
@@ -12,6 +12,7 @@ out vec3 v2f_frag_world_pos;
 out vec4 v2f_frag_light_space_pos;
 out vec2 v2f_tex_coord;
 out vec3 v2f_normal;
+out float v2f_view_distance;
 
 #define JITTER_RESOLUTION vec2(160, 120)
 
@@ -23,6 +24,7 @@ void main()
     v2f_normal = normalize(mat3(transpose(inverse(u_model))) * in_normal);  
 
     v2f_tex_coord = in_tex_coord;
+    v2f_view_distance = length((u_view * vec4(v2f_frag_world_pos, 1.0)).xyz);
 
     vec4 clip_pos = u_projection * u_view * u_model * vec4(in_position, 1.0);
 
@@ -58,10 +60,17 @@ uniform PointLight u_point_lights[MAX_LIGHT_COUNT];
 uniform int u_point_light_count;
 uniform float u_far_plane;
 
+uniform vec4 u_tint;
+
+uniform int u_underwater;
+uniform vec3 u_water_fog_color;
+uniform float u_water_fog_density;
+
 in vec3 v2f_frag_world_pos;
 in vec4 v2f_frag_light_space_pos;
 in vec2 v2f_tex_coord;
 in vec3 v2f_normal;
+in float v2f_view_distance;
 
 out vec4 out_color;
 
@@ -150,5 +159,14 @@ void main() {
     }
 
     out_color = mix(shadowed_tex_color, tex_color, brightness);
+
+    if (u_underwater == 1) {
+        // Tint towards the water color, then fade into it with distance
+        float fog_amount = exp(-u_water_fog_density * v2f_view_distance);
+        vec3 tinted = out_color.rgb * (u_water_fog_color * 2.0 + 0.5);
+        out_color.rgb = mix(u_water_fog_color, tinted, fog_amount);
+    }
+
+    out_color *= u_tint;
 }
 #endif
@@ -15,6 +15,7 @@ void main()
 
 #ifdef FRAGMENT
 layout(binding=0) uniform sampler2D texture_ui;
+uniform vec4 u_tint;
 
 in VS_OUTPUT {
     vec2 TexCoord;
@@ -24,6 +25,6 @@ out vec4 Color;
 
 void main()
 {
-    Color = texture(texture_ui, IN.TexCoord);
+    Color = texture(texture_ui, IN.TexCoord) * u_tint;
 }
 #endif
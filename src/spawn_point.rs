@@ -0,0 +1,32 @@
+use cgmath::*;
+use serde::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub position: [f32; 3],
+    pub facing: [f32; 3],
+}
+
+impl SpawnPoint {
+    pub fn position(&self) -> Point3<f32> {
+        self.position.into()
+    }
+
+    pub fn facing(&self) -> Vector3<f32> {
+        let facing = Vector3::from(self.facing);
+        if facing.magnitude2() > 0.00001 {
+            facing.normalize()
+        } else {
+            -Vector3::unit_z()
+        }
+    }
+}
+
+impl Default for SpawnPoint {
+    fn default() -> SpawnPoint {
+        SpawnPoint {
+            position: [-5.0, 2.0, 5.0],
+            facing: [0.0, 0.0, -1.0],
+        }
+    }
+}
@@ -8,6 +8,8 @@ pub struct StaticObject<'a> {
     pub material: &'a Material,
     pub mesh: &'a Mesh,
     pub triangles: Vec<Triangle>,
+    pub climbable: bool,
+    pub object_index: usize, // The scene object it's a part of, in World::objects
 }
 
 impl<'a> StaticObject<'a> {
@@ -15,6 +17,8 @@ impl<'a> StaticObject<'a> {
         mesh: &'a Mesh,
         material: &'a Material,
         transform: Matrix4<f32>,
+        climbable: bool,
+        object_index: usize,
     ) -> StaticObject<'a> {
         let triangles = mesh
             .triangles
@@ -27,6 +31,8 @@ impl<'a> StaticObject<'a> {
             material: material,
             mesh: mesh,
             triangles: triangles,
+            climbable,
+            object_index,
         }
     }
 
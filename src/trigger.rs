@@ -0,0 +1,96 @@
+use crate::geom::Aabb;
+use crate::physics::capsule_overlaps_aabb;
+use crate::spawn_point::SpawnPoint;
+use cgmath::*;
+use serde::*;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TriggerKind {
+    Start,
+    Split,
+    Finish,
+    Checkpoint(SpawnPoint),
+    Launch(Launch),
+    Boost(f32), // Multiplies the horizontal speed
+    Teleport(Teleporter),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Launch {
+    Set([f32; 3]), // Replaces the player's velocity
+    Add([f32; 3]),
+    // Arcs the player onto a target point, peaking apex_height above the higher end
+    ToTarget { target: String, apex_height: f32 },
+}
+
+// Teleporters are paired by trigger name, each one holds where players arriving through it appear
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Teleporter {
+    #[serde(default)]
+    pub destination: Option<String>, // Exit-only teleporters don't send the player anywhere
+    pub exit: SpawnPoint,
+    #[serde(default)]
+    pub momentum: TeleportMomentum,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum TeleportMomentum {
+    Keep, // Same velocity and view, only the position changes
+    #[default]
+    Rotate, // View turns to the exit's facing, velocity turns along with it
+    Zero, // Arrives standing still, facing the exit's facing
+}
+
+// A named position in the scene that other objects can refer to
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetPoint {
+    pub name: String,
+    pub position: Point3<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriggerEvent {
+    Entered,
+    Exited,
+}
+
+pub struct Trigger {
+    pub name: String,
+    pub bounds: Aabb,
+    pub kind: TriggerKind,
+    is_overlapping: bool,
+}
+
+impl Trigger {
+    pub fn new(name: String, bounds: Aabb, kind: TriggerKind) -> Trigger {
+        Trigger {
+            name,
+            bounds,
+            kind,
+            is_overlapping: false,
+        }
+    }
+
+    // Forgets the overlap, so that a player already inside gets an Entered event on the next update
+    pub fn reset(&mut self) {
+        self.is_overlapping = false;
+    }
+
+    // Treats the player as already inside, so that arriving in a teleporter doesn't send them back
+    pub fn mark_entered(&mut self) {
+        self.is_overlapping = true;
+    }
+
+    // Only reports the frames where the overlap state changes
+    pub fn update(&mut self, player_pos: Point3<f32>) -> Option<TriggerEvent> {
+        let is_overlapping = capsule_overlaps_aabb(player_pos, self.bounds);
+        let event = match (self.is_overlapping, is_overlapping) {
+            (false, true) => Some(TriggerEvent::Entered),
+            (true, false) => Some(TriggerEvent::Exited),
+            _ => None,
+        };
+
+        self.is_overlapping = is_overlapping;
+        event
+    }
+}
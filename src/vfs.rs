@@ -0,0 +1,369 @@
+// Asset paths like "assets/scene.json" are virtual, they're looked up through the mounts instead of the
+// working directory. Mounts are directories or zip archives (".pack" is the shipped data pack), and
+// later mounts override the earlier ones file by file, which is how mods replace single assets.
+use memmap2::Mmap;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use zip::ZipArchive;
+
+const DATA_MARKER: &str = "assets/levels.json"; // A directory with this in it holds the loose game data
+const PACK_NAME: &str = "data.pack";
+const MODS_DIR: &str = "mods";
+
+enum Mount {
+    Directory(PathBuf),
+    Archive(Archive),
+}
+
+struct Archive {
+    path: PathBuf,
+    zip: RefCell<ZipArchive<fs::File>>, // Reading an entry needs the archive mutably
+    modified: Option<SystemTime>,       // Every entry counts as changed when the archive does
+}
+
+#[derive(Default)]
+pub struct Vfs {
+    mounts: Vec<Mount>,
+}
+
+// The contents of a file, mapped when it's loose on disk and read out when it's inside an archive
+pub enum FileData {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Vfs {
+    pub fn new() -> Vfs {
+        Vfs::default()
+    }
+
+    // The data root is the first directory with the loose data or the data pack in it, searched from the
+    // working directory and then upwards from the executable, so that the game runs from anywhere.
+    // The pack is mounted under the loose files, and every mod in the mods directory on top in name order.
+    pub fn mount_game_data() -> io::Result<Vfs> {
+        let data_root = find_data_root().ok_or_else(|| {
+            not_found(format!(
+                "neither {} nor {} is in the working directory or above the executable",
+                DATA_MARKER, PACK_NAME
+            ))
+        })?;
+
+        let mut vfs = Vfs::new();
+        let pack_path = data_root.join(PACK_NAME);
+        if pack_path.is_file() {
+            vfs.mount_archive(&pack_path)?;
+        }
+        vfs.mount_directory(&data_root);
+
+        if let Ok(entries) = fs::read_dir(data_root.join(MODS_DIR)) {
+            let mut mod_paths: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+            mod_paths.sort();
+            for mod_path in mod_paths {
+                if mod_path.is_dir() {
+                    vfs.mount_directory(&mod_path);
+                } else if is_archive(&mod_path) {
+                    vfs.mount_archive(&mod_path)?;
+                }
+            }
+        }
+        Ok(vfs)
+    }
+
+    pub fn mount_directory(&mut self, dir: &Path) {
+        self.mounts.push(Mount::Directory(dir.to_path_buf()));
+    }
+
+    pub fn mount_archive(&mut self, path: &Path) -> io::Result<()> {
+        let file = fs::File::open(path)?;
+        let modified = file.metadata().and_then(|m| m.modified()).ok();
+        let zip = ZipArchive::new(file).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })?;
+        self.mounts.push(Mount::Archive(Archive {
+            path: path.to_path_buf(),
+            zip: RefCell::new(zip),
+            modified,
+        }));
+        Ok(())
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        match self.find(path) {
+            Some((Mount::Directory(dir), entry)) => fs::read(dir.join(entry)),
+            Some((Mount::Archive(archive), entry)) => archive.read(&entry),
+            None => Err(missing(path)),
+        }
+    }
+
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> io::Result<String> {
+        let bytes = self.read(path)?;
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn map(&self, path: impl AsRef<Path>) -> io::Result<FileData> {
+        let path = path.as_ref();
+        match self.find(path) {
+            Some((Mount::Directory(dir), entry)) => {
+                let file = fs::File::open(dir.join(entry))?;
+                // Files that are written while the game runs are replaced instead, see the cook binary
+                Ok(FileData::Mapped(unsafe { Mmap::map(&file)? }))
+            }
+            Some((Mount::Archive(archive), entry)) => archive.read(&entry).map(FileData::Read),
+            None => Err(missing(path)),
+        }
+    }
+
+    // None for missing files, so that a file appearing counts as a change
+    pub fn modified(&self, path: impl AsRef<Path>) -> Option<SystemTime> {
+        match self.find(path.as_ref())? {
+            (Mount::Directory(dir), entry) => fs::metadata(dir.join(entry))
+                .and_then(|m| m.modified())
+                .ok(),
+            (Mount::Archive(archive), _) => archive.modified,
+        }
+    }
+
+    // The files directly in a directory, merged from every mount
+    pub fn read_dir(&self, dir: impl AsRef<Path>) -> Vec<PathBuf> {
+        let dir = match normalize(dir.as_ref()) {
+            Some(dir) => dir,
+            None => return Vec::new(),
+        };
+        let prefix = if dir.is_empty() {
+            dir.clone()
+        } else {
+            format!("{}/", dir)
+        };
+
+        let mut files = BTreeSet::new();
+        for mount in &self.mounts {
+            match mount {
+                Mount::Directory(root) => {
+                    if let Ok(entries) = fs::read_dir(root.join(&dir)) {
+                        for entry in entries.flatten().filter(|e| e.path().is_file()) {
+                            let name = entry.file_name().to_string_lossy().into_owned();
+                            files.insert(format!("{}{}", prefix, name));
+                        }
+                    }
+                }
+                Mount::Archive(archive) => {
+                    let zip = archive.zip.borrow();
+                    for name in zip.file_names() {
+                        if let Some(name) = name.strip_prefix(&prefix) {
+                            if !name.is_empty() && !name.contains('/') {
+                                files.insert(format!("{}{}", prefix, name));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        files.into_iter().map(PathBuf::from).collect()
+    }
+
+    // Where on disk a write to the file should go so that it's what gets read back. That's the loose file
+    // that's read now, or a new one in the lowest directory above the archive that has it. New files go to
+    // the lowest directory, which is the data root.
+    pub fn writable_path(&self, path: impl AsRef<Path>) -> io::Result<PathBuf> {
+        let path = path.as_ref();
+        let entry = normalize(path).ok_or_else(|| missing(path))?;
+        let mut lowest_directory = None;
+        for mount in self.mounts.iter().rev() {
+            match mount {
+                Mount::Directory(dir) => {
+                    if dir.join(&entry).is_file() {
+                        return Ok(dir.join(&entry));
+                    }
+                    lowest_directory = Some(dir);
+                }
+                Mount::Archive(archive) if archive.contains(&entry) => break,
+                Mount::Archive(_) => {}
+            }
+        }
+        lowest_directory
+            .map(|dir| dir.join(&entry))
+            .ok_or_else(|| not_found(format!("no directory above {} to write into", entry)))
+    }
+
+    // The last mount that has the file, with the file's path within it
+    fn find(&self, path: &Path) -> Option<(&Mount, String)> {
+        let entry = normalize(path)?;
+        self.mounts
+            .iter()
+            .rev()
+            .find(|mount| match mount {
+                Mount::Directory(dir) => dir.join(&entry).is_file(),
+                Mount::Archive(archive) => archive.contains(&entry),
+            })
+            .map(|mount| (mount, entry))
+    }
+}
+
+impl Archive {
+    fn contains(&self, entry: &str) -> bool {
+        self.zip.borrow().file_names().any(|name| name == entry)
+    }
+
+    fn read(&self, entry: &str) -> io::Result<Vec<u8>> {
+        let mut zip = self.zip.borrow_mut();
+        let mut file = zip.by_name(entry).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} in {}: {}", entry, self.path.display(), e),
+            )
+        })?;
+        let mut bytes = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Deref for FileData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileData::Mapped(mapped) => mapped,
+            FileData::Read(bytes) => bytes,
+        }
+    }
+}
+
+// The mounts from the lowest to the highest
+impl fmt::Display for Vfs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mounts: Vec<String> = self
+            .mounts
+            .iter()
+            .map(|mount| match mount {
+                Mount::Directory(dir) => dir.display().to_string(),
+                Mount::Archive(archive) => archive.path.display().to_string(),
+            })
+            .collect();
+        write!(f, "{}", mounts.join(", "))
+    }
+}
+
+// Forward slashes with "." and ".." resolved, None for paths that leave the mount or are absolute
+pub fn normalize(path: &Path) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn find_data_root() -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = env::current_dir().into_iter().collect();
+    if let Ok(exe_path) = env::current_exe() {
+        candidates.extend(exe_path.ancestors().skip(1).map(Path::to_path_buf));
+    }
+    candidates
+        .into_iter()
+        .find(|dir| dir.join(DATA_MARKER).is_file() || dir.join(PACK_NAME).is_file())
+}
+
+fn is_archive(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension == "pack" || extension == "zip",
+        None => false,
+    }
+}
+
+fn missing(path: &Path) -> io::Error {
+    not_found(format!("{} isn't in any mount", path.display()))
+}
+
+fn not_found(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("./assets/../assets/a.png")),
+            Some("assets/a.png".to_string())
+        );
+        assert_eq!(normalize(Path::new("../a.png")), None);
+    }
+
+    #[test]
+    fn test_later_mounts_override_and_write_where_read() {
+        let base = test_dir("hell_vfs_base");
+        let overlay = test_dir("hell_vfs_overlay");
+        fs::write(base.join("assets/a.txt"), "base a").unwrap();
+        fs::write(base.join("assets/b.txt"), "base b").unwrap();
+        fs::write(overlay.join("assets/b.txt"), "mod b").unwrap();
+
+        let archive_path = overlay.join("mod.pack");
+        let mut writer = ZipWriter::new(fs::File::create(&archive_path).unwrap());
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("assets/c.txt", options).unwrap();
+        writer.write_all(b"pack c").unwrap();
+        writer.finish().unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.mount_directory(&base);
+        vfs.mount_directory(&overlay);
+        vfs.mount_archive(&archive_path).unwrap();
+
+        assert_eq!(vfs.read_to_string("assets/a.txt").unwrap(), "base a");
+        assert_eq!(vfs.read_to_string("assets/b.txt").unwrap(), "mod b");
+        assert_eq!(&*vfs.map("assets/c.txt").unwrap(), b"pack c");
+        assert!(vfs.read("assets/d.txt").is_err());
+        assert_eq!(
+            vfs.read_dir("assets"),
+            vec![
+                PathBuf::from("assets/a.txt"),
+                PathBuf::from("assets/b.txt"),
+                PathBuf::from("assets/c.txt")
+            ]
+        );
+
+        assert_eq!(
+            vfs.writable_path("assets/b.txt").unwrap(),
+            overlay.join("assets/b.txt")
+        );
+        assert_eq!(
+            vfs.writable_path("assets/d.txt").unwrap(),
+            base.join("assets/d.txt")
+        );
+        assert!(vfs.writable_path("assets/c.txt").is_err());
+
+        fs::remove_dir_all(&base).unwrap();
+        fs::remove_dir_all(&overlay).unwrap();
+    }
+}
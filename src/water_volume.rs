@@ -0,0 +1,20 @@
+use crate::geom::Aabb;
+use cgmath::*;
+
+pub struct WaterVolume {
+    pub bounds: Aabb,
+}
+
+impl WaterVolume {
+    pub fn new(bounds: Aabb) -> WaterVolume {
+        WaterVolume { bounds }
+    }
+
+    pub fn surface_height(&self) -> f32 {
+        self.bounds.max.y
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        self.bounds.contains(point)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::light_source::PointLightSource;
+#[cfg(test)]
+use crate::mesh::Mesh;
+#[cfg(test)]
+use crate::render::material::Material;
+use crate::spawn_point::SpawnPoint;
+use crate::static_object::StaticObject;
+use crate::trigger::{TargetPoint, Trigger, TriggerEvent};
+use crate::water_volume::WaterVolume;
+use cgmath::*;
+
+// Everything that's instantiated from a scene file
+pub struct World<'a> {
+    pub objects: Vec<SceneObject>,
+    pub static_objects: Vec<StaticObject<'a>>,
+    pub water_volumes: Vec<WaterVolume>,
+    pub triggers: Vec<Trigger>,
+    pub target_points: Vec<TargetPoint>,
+    pub point_lights: Vec<PointLightSource>,
+    pub spawn_point: SpawnPoint,
+    pub kill_height: f32, // Falling below this respawns the player
+    pub next_level: Option<String>,
+}
+
+// A prefab instance as it's placed in the scene file, its meshes end up in static_objects
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneObject {
+    pub name: String,
+    pub prefab_name: String,
+    pub position: [f32; 3],
+    pub rotation: [f32; 3], // Euler angles in degrees, applied in x, y, z order
+    pub scale: [f32; 3],
+    pub climbable: bool,
+}
+
+impl SceneObject {
+    pub fn transform(&self) -> Matrix4<f32> {
+        let [x, y, z] = self.rotation;
+        let rotation = Matrix4::from_angle_z(Deg(z))
+            * Matrix4::from_angle_y(Deg(y))
+            * Matrix4::from_angle_x(Deg(x));
+        let [sx, sy, sz] = self.scale;
+        Matrix4::from_translation(self.position.into())
+            * rotation
+            * Matrix4::from_nonuniform_scale(sx, sy, sz)
+    }
+}
+
+impl World<'_> {
+    pub fn water_at(&self, point: Point3<f32>) -> Option<&WaterVolume> {
+        self.water_volumes.iter().find(|w| w.contains(point))
+    }
+
+    pub fn trigger_index(&self, name: &str) -> Option<usize> {
+        self.triggers.iter().position(|t| t.name == name)
+    }
+
+    pub fn target_point(&self, name: &str) -> Option<Point3<f32>> {
+        self.target_points
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| t.position)
+    }
+
+    // Returns the indices of the triggers that the player entered or exited this frame
+    pub fn update_triggers(&mut self, player_pos: Point3<f32>) -> Vec<(usize, TriggerEvent)> {
+        self.triggers
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, trigger)| trigger.update(player_pos).map(|event| (i, event)))
+            .collect()
+    }
+
+    pub fn reset_triggers(&mut self) {
+        for trigger in self.triggers.iter_mut() {
+            trigger.reset();
+        }
+    }
+}
+
+// Collision only, for testing movement without a GL context. Each surface is a quad with whether it
+// can be climbed, its corners go counterclockwise seen from the side that it pushes the player to.
+#[cfg(test)]
+pub fn test_world(surfaces: &[([[f32; 3]; 4], bool)]) -> World<'static> {
+    let material: &'static Material = Box::leak(Box::new(Material::placeholder("test")));
+    let static_objects = surfaces
+        .iter()
+        .enumerate()
+        .map(|(i, (corners, climbable))| {
+            let positions: Vec<f32> = corners.iter().flatten().copied().collect();
+            let mesh = Mesh::from_indexed(&positions, &[0, 1, 2, 0, 2, 3]);
+            let mesh: &'static Mesh = Box::leak(Box::new(mesh));
+            StaticObject::new(mesh, material, Matrix4::identity(), *climbable, i)
+        })
+        .collect();
+
+    World {
+        objects: Vec::new(),
+        static_objects,
+        water_volumes: Vec::new(),
+        triggers: Vec::new(),
+        target_points: Vec::new(),
+        point_lights: Vec::new(),
+        spawn_point: SpawnPoint::default(),
+        kill_height: -100.0,
+        next_level: None,
+    }
+}